@@ -0,0 +1,91 @@
+// Adds a button to the first line of each command for copying the command the way
+// it is typed, with the glyphs turned back into symbols like \<alpha>, so that it
+// can be pasted into a theory.
+(function () {
+    "use strict";
+
+    var data = document.getElementById("isabelle-symbols");
+    if (!data || !navigator.clipboard) {
+        return;
+    }
+
+    var symbols = JSON.parse(data.textContent);
+    // Longer glyphs first, so that the ones with a variation selector are found.
+    var glyphs = Object.keys(symbols).sort(function (a, b) {
+        return b.length - a.length;
+    });
+    var pattern = new RegExp(glyphs.map(function (glyph) {
+        return glyph.replace(/[.*+?^${}()|[\]\\]/g, "\\$&");
+    }).join("|"), "g");
+
+    // The elements that control symbols are written as, and the ranges written as
+    // subscripts or superscripts.
+    var controls = { SUB: "\\<^sub>", SUP: "\\<^sup>", B: "\\<^bold>" };
+    var ranges = { SUB: ["\\<^bsub>", "\\<^esub>"], SUP: ["\\<^bsup>", "\\<^esup>"] };
+    var single = /^(\\<[^>]*>|.)$/u;
+
+    function source(node) {
+        if (node.nodeType === Node.TEXT_NODE) {
+            return node.data
+                .replace(/\u200c/g, "")
+                .replace(pattern, function (glyph) { return symbols[glyph]; });
+        }
+        if (node.nodeType !== Node.ELEMENT_NODE
+            || node.matches(".tooltip, .annotation, .symbol-ascii, button")) {
+            return "";
+        }
+
+        var text = Array.prototype.map.call(node.childNodes, source).join("");
+        if (node.classList.contains("control")) {
+            return "\\<^" + text + ">";
+        }
+        if (controls[node.tagName] && single.test(text)) {
+            return controls[node.tagName] + text;
+        }
+        if (ranges[node.tagName]) {
+            return ranges[node.tagName][0] + text + ranges[node.tagName][1];
+        }
+        return text;
+    }
+
+    // The lines of the command starting at `first`.
+    function command(first) {
+        var lines = document.querySelectorAll(".isabelle-code code");
+        var text = [];
+        var i = Array.prototype.indexOf.call(lines, first);
+        do {
+            text.push(source(lines[i]));
+            i++;
+        } while (i < lines.length && !lines[i].hasAttribute("data-command"));
+        return text.join("\n");
+    }
+
+    function addButtons() {
+        var firsts = document.querySelectorAll(".isabelle-code code[data-command]");
+        Array.prototype.forEach.call(firsts, function (first) {
+            if (first.querySelector(".copy-ascii")) {
+                return;
+            }
+            var button = document.createElement("button");
+            button.type = "button";
+            button.className = "copy-ascii";
+            button.title = "Copy with the symbols as they are typed";
+            button.addEventListener("click", function () {
+                navigator.clipboard.writeText(command(first)).then(function () {
+                    button.classList.add("copied");
+                    setTimeout(function () {
+                        button.classList.remove("copied");
+                    }, 2000);
+                });
+            });
+            first.appendChild(button);
+        });
+    }
+
+    addButtons();
+    // Pages loaded by lazy.js get buttons as well.
+    new MutationObserver(addButtons).observe(document.body, {
+        childList: true,
+        subtree: true
+    });
+})();
@@ -0,0 +1,45 @@
+// Loads the following pages of a paginated theory as the reader scrolls to the
+// end of the current one. Without JavaScript, the page navigation links are used.
+(function () {
+    "use strict";
+
+    var container = document.querySelector(".isabelle-code, .side-by-side");
+    var nav = document.querySelector("nav.pages");
+    if (!container || !nav || !window.IntersectionObserver) {
+        return;
+    }
+
+    var next = nav.querySelector("a[rel=next]");
+    var loading = false;
+    var sentinel = document.createElement("div");
+    container.parentNode.insertBefore(sentinel, container.nextSibling);
+
+    var observer = new IntersectionObserver(function (entries) {
+        if (!entries[0].isIntersecting || loading || !next) {
+            return;
+        }
+
+        loading = true;
+        fetch(next.href)
+            .then(function (response) { return response.text(); })
+            .then(function (html) {
+                var page = new DOMParser().parseFromString(html, "text/html");
+                var content = page.querySelector(".isabelle-code, .side-by-side");
+                var target = container.tBodies ? container.tBodies[0] : container;
+                var source = content.tBodies ? content.tBodies[0] : content;
+                while (source.firstChild) {
+                    target.appendChild(document.adoptNode(source.firstChild));
+                }
+
+                next = page.querySelector("nav.pages a[rel=next]");
+                if (!next) {
+                    observer.disconnect();
+                }
+                loading = false;
+            })
+            .catch(function () {
+                observer.disconnect();
+            });
+    }, { rootMargin: "1000px" });
+    observer.observe(sentinel);
+})();
@@ -0,0 +1,19 @@
+// Adds a button to each line that is too long to show in full, for wrapping it
+// instead of scrolling. Without JavaScript, the lines can still be scrolled.
+(function () {
+    "use strict";
+
+    var lines = document.querySelectorAll(".long-line");
+    for (var i = 0; i < lines.length; i++) {
+        var button = document.createElement("button");
+        button.type = "button";
+        button.className = "expand-line";
+        button.title = "Show the whole line";
+        button.addEventListener("click", function (event) {
+            var line = event.currentTarget.parentNode;
+            var expanded = line.classList.toggle("expanded");
+            event.currentTarget.title = expanded ? "Scroll the line instead" : "Show the whole line";
+        });
+        lines[i].insertBefore(button, lines[i].firstChild);
+    }
+})();
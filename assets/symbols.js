@@ -0,0 +1,39 @@
+// Adds a button for switching between showing symbols as Unicode and as they are
+// typed, like \<alpha>. The choice is remembered for the other pages.
+(function () {
+    "use strict";
+
+    var code = document.querySelector(".isabelle-code, .side-by-side");
+    if (!code) {
+        return;
+    }
+
+    var root = document.documentElement;
+    var button = document.createElement("button");
+    button.type = "button";
+    button.className = "symbol-toggle";
+
+    function show(mode) {
+        root.dataset.symbols = mode;
+        button.textContent = mode === "ascii" ? "Show Unicode" : "Show ASCII";
+    }
+
+    var saved = null;
+    try {
+        saved = window.localStorage.getItem("isabelle-symbols");
+    } catch (e) {
+        // Storage may be unavailable, like for pages opened from files.
+    }
+    show(saved === "ascii" ? "ascii" : "unicode");
+
+    button.addEventListener("click", function () {
+        var mode = root.dataset.symbols === "ascii" ? "unicode" : "ascii";
+        show(mode);
+        try {
+            window.localStorage.setItem("isabelle-symbols", mode);
+        } catch (e) {
+            // The choice just isn't remembered then.
+        }
+    });
+    code.parentNode.insertBefore(button, code);
+})();
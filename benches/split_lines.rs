@@ -0,0 +1,56 @@
+//! Splitting the IR of a large theory into lines, with spans that cover many lines
+//! and carry long tooltips, like the types of big terms in a proof state.
+//!
+//! Run with `cargo bench --bench split_lines`.
+
+use isabelle_markup::ir::{split_lines, Tag, TagTree};
+use std::hint::black_box;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A theory of `commands` commands, each `lines` lines long, with a tooltip of
+/// `tooltip` bytes on the whole command and classes on each line.
+fn theory(commands: usize, lines: usize, tooltip: usize) -> Vec<TagTree<'static>> {
+    let command = TagTree::Tag {
+        tag: Rc::new(Tag::Tooltip("x".repeat(tooltip))),
+        children: (0..lines)
+            .flat_map(|_| {
+                [
+                    TagTree::Tag {
+                        tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                        children: vec![TagTree::Text("x")],
+                    },
+                    TagTree::Text(" + y\n"),
+                ]
+            })
+            .collect(),
+    };
+    vec![command; commands]
+}
+
+/// The fastest of a few runs of `f`.
+fn time(f: impl Fn()) -> Duration {
+    (0..10)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    for &(commands, lines, tooltip) in
+        &[(1000, 10, 100), (100, 100, 1000), (10, 1000, 10000)]
+    {
+        let ir = theory(commands, lines, tooltip);
+        let elapsed = time(|| {
+            black_box(split_lines(black_box(&ir)));
+        });
+        println!(
+            "{} commands of {} lines, {} byte tooltips: {:?}",
+            commands, lines, tooltip, elapsed
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Loading an abstract to show above the theory, to give readers some context.
+//!
+//! HTML files are included as they are. For LaTeX, like the `document/root.tex` of
+//! an AFP entry, the `abstract` environment is extracted. Anything else, like
+//! Markdown, is shown as plain paragraphs.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The simple formatting commands that can be carried over from LaTeX.
+static FORMATTING_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\\(emph|textit|textbf|texttt)\{([^{}]*)\}").unwrap());
+
+/// Load the abstract at `path`, as HTML.
+pub fn load(path: &Path) -> io::Result<String> {
+    let text = fs::read_to_string(path)?;
+    let abstract_html = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html" | "htm") => text,
+        Some("tex") => from_latex(&text).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no abstract environment in {}", path.display()),
+            )
+        })?,
+        _ => paragraphs(&text),
+    };
+    Ok(abstract_html)
+}
+
+/// Split the text into paragraphs at blank lines.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|para| !para.is_empty())
+        .map(|para| format!("<p>{}</p>", html_escape::encode_text(para)))
+        .collect()
+}
+
+/// The contents of the `abstract` environment, without comments or escapes, and
+/// with the simplest formatting commands turned into HTML.
+fn from_latex(tex: &str) -> Option<String> {
+    let start = tex.find(r"\begin{abstract}")? + r"\begin{abstract}".len();
+    let end = start + tex[start..].find(r"\end{abstract}")?;
+    let text: Vec<&str> = tex[start..end]
+        .lines()
+        .map(|line| match line.find('%') {
+            Some(i) if !line[..i].ends_with('\\') => &line[..i],
+            _ => line,
+        })
+        .collect();
+
+    let mut text = text.join("\n");
+    for escaped in ["%", "&", "_", "#", "$"] {
+        text = text.replace(&format!("\\{}", escaped), escaped);
+    }
+
+    let html = paragraphs(&text);
+    let html = FORMATTING_RE.replace_all(&html, |captures: &regex::Captures<'_>| {
+        let tag = match &captures[1] {
+            "emph" | "textit" => "em",
+            "textbf" => "strong",
+            _ => "code",
+        };
+        format!("<{}>{}</{}>", tag, &captures[2], tag)
+    });
+    Some(html.into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latex_abstract() {
+        let tex = r"\title{Foo}
+\begin{abstract}
+  We formalize \emph{foo} % in HOL
+  and bar.
+
+  It takes 100\% less time than \texttt{x < y}.
+\end{abstract}
+\tableofcontents";
+        assert_eq!(
+            from_latex(tex).unwrap(),
+            "<p>We formalize <em>foo</em> \n  and bar.</p>\
+             <p>It takes 100% less time than <code>x &lt; y</code>.</p>"
+        );
+        assert_eq!(from_latex(r"\title{Foo}"), None);
+    }
+}
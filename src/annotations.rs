@@ -0,0 +1,88 @@
+//! Notes attached to the rendered code from a separate file, so that proofs can be
+//! annotated without modifying the theory sources.
+//!
+//! The file contains a JSON array of objects, each with a `note`, and either the
+//! `line` it belongs to (counting from 1), or the name of the `theorem` whose first
+//! line it should be attached to:
+//!
+//! ```json
+//! [
+//!   { "theorem": "foo", "note": "The induction is on the length of the list." },
+//!   { "line": 12, "note": "This step is where the lemma above is needed." }
+//! ]
+//! ```
+
+use isabelle_markup::model::Theory;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Annotation {
+    line: Option<usize>,
+    theorem: Option<String>,
+    note: String,
+}
+
+/// The notes to attach to each line, where lines are counted from 0.
+pub type Annotations = HashMap<usize, Vec<String>>;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn resolve(annotations: Vec<Annotation>, theory: &Theory<'_>) -> io::Result<Annotations> {
+    let mut lines = Annotations::new();
+    for annotation in annotations {
+        let line = match (annotation.line, &annotation.theorem) {
+            (Some(line), None) if line > 0 => line,
+            (None, Some(name)) => {
+                theory
+                    .commands
+                    .iter()
+                    .find(|cmd| cmd.theorem_name().as_ref() == Some(name))
+                    .ok_or_else(|| invalid(format!("no theorem named {:?}", name)))?
+                    .line
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "annotation {:?} needs either a line number or a theorem name",
+                    annotation.note
+                )))
+            }
+        };
+
+        lines.entry(line - 1).or_default().push(annotation.note);
+    }
+
+    Ok(lines)
+}
+
+pub fn load(path: &Path, theory: &Theory<'_>) -> io::Result<Annotations> {
+    let annotations = serde_json::from_slice(&std::fs::read(path)?)?;
+    resolve(annotations, theory)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_theorems() {
+        let input = "\x05\x06command_span\x06name=lemma\x05lemma foo: x\x05\x06\x05\n\
+            \x05\x06command_span\x06name=lemma\x05lemma bar: y\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+        let theory = Theory::from_markup(&nodes);
+        let annotations = serde_json::from_str(
+            r#"[{"theorem": "bar", "note": "a"}, {"line": 2, "note": "b"}]"#,
+        )
+        .unwrap();
+        let lines = resolve(annotations, &theory).unwrap();
+        assert_eq!(lines[&1], ["a", "b"]);
+
+        let annotations = serde_json::from_str(r#"[{"theorem": "baz", "note": "a"}]"#);
+        assert!(resolve(annotations.unwrap(), &theory).is_err());
+    }
+}
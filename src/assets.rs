@@ -0,0 +1,39 @@
+//! The files in `assets/` that the rendered pages refer to.
+
+use sha2::{Digest, Sha384};
+
+/// The stylesheet the classes are styled with.
+pub static STYLESHEET: &str = include_str!("../assets/isabelle.css");
+
+/// The script that loads further pages of paginated output on scroll.
+pub static LAZY_SCRIPT: &str = include_str!("../assets/lazy.js");
+
+/// The script that lets readers expand lines that are too long to show in full.
+pub static LINES_SCRIPT: &str = include_str!("../assets/lines.js");
+
+/// The script that switches between showing symbols as Unicode and as they are
+/// typed.
+pub static SYMBOLS_SCRIPT: &str = include_str!("../assets/symbols.js");
+
+/// The script that adds buttons for copying commands the way they are typed.
+pub static COPY_SCRIPT: &str = include_str!("../assets/copy.js");
+
+/// A subresource integrity hash of an asset, for the `integrity` attribute of the
+/// element referring to it.
+pub fn integrity(asset: &str) -> String {
+    let digest = Sha384::digest(asset.as_bytes());
+    format!("sha384-{}", base64::encode(digest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn integrity_format() {
+        let integrity = integrity(STYLESHEET);
+        assert!(integrity.starts_with("sha384-"));
+        // 48 bytes of digest, base64-encoded without any padding.
+        assert_eq!(integrity.len(), "sha384-".len() + 64);
+    }
+}
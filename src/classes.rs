@@ -0,0 +1,210 @@
+//! Alternative naming schemes for the classes attached to highlighted spans, so that
+//! the output can be styled with stylesheets written for other highlighters.
+
+use crate::assets::STYLESHEET;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassStyle {
+    /// The names of the Isabelle markup elements, as styled by `isabelle.css`.
+    Isabelle,
+    /// The short class names used by Pygments.
+    Pygments,
+    /// The `hljs-*` class names used by highlight.js.
+    Highlightjs,
+}
+
+impl FromStr for ClassStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "isabelle" => Ok(ClassStyle::Isabelle),
+            "pygments" => Ok(ClassStyle::Pygments),
+            "highlightjs" => Ok(ClassStyle::Highlightjs),
+            _ => Err(format!("unknown class style: {}", s)),
+        }
+    }
+}
+
+impl ClassStyle {
+    /// The name of the style, as accepted by `FromStr`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClassStyle::Isabelle => "isabelle",
+            ClassStyle::Pygments => "pygments",
+            ClassStyle::Highlightjs => "highlightjs",
+        }
+    }
+
+    /// The class to put on the `<pre>` element, which stylesheets for this style
+    /// expect the code to be nested inside of.
+    pub fn container_class(self) -> Option<&'static str> {
+        match self {
+            ClassStyle::Isabelle => None,
+            ClassStyle::Pygments => Some("highlight"),
+            ClassStyle::Highlightjs => Some("hljs"),
+        }
+    }
+
+    /// Translate a single class. Classes without an equivalent are dropped.
+    pub fn translate(self, class: &str) -> Option<&str> {
+        match self {
+            ClassStyle::Isabelle => Some(class),
+            ClassStyle::Pygments => pygments_class(class),
+            ClassStyle::Highlightjs => highlightjs_class(class),
+        }
+    }
+}
+
+/// How the classes of spans are emitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StyleMode {
+    /// As `class` attributes, to be styled by a stylesheet.
+    Classes,
+    /// As `style` attributes, with the declarations taken from `isabelle.css`. This
+    /// is meant for targets that strip stylesheets, and as such, can't show tooltips.
+    Inline,
+}
+
+impl StyleMode {
+    /// The attribute to put on an element with the given classes, including a
+    /// leading space, or an empty string if there's nothing to emit.
+    pub fn attr(self, classes: &str) -> String {
+        match self {
+            StyleMode::Classes => format!(r#" class="{}""#, classes),
+            StyleMode::Inline => {
+                let style = classes
+                    .split_whitespace()
+                    .filter_map(|cls| INLINE_STYLES.get(cls))
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if style.is_empty() {
+                    String::new()
+                } else {
+                    format!(r#" style="{}""#, style)
+                }
+            }
+        }
+    }
+}
+
+static INLINE_STYLES: Lazy<HashMap<&'static str, String>> = Lazy::new(parse_stylesheet);
+
+/// Collect the declarations of the rules in `isabelle.css` whose selectors are plain
+/// class names. Anything more complicated, like `:hover`, can't be inlined anyway.
+fn parse_stylesheet() -> HashMap<&'static str, String> {
+    static RULE_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?m)^([.\w\s,-]+)\{([^}]*)\}").unwrap());
+    static CLASS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\.[\w-]+$").unwrap());
+
+    let mut styles: HashMap<&'static str, String> = HashMap::new();
+    for captures in RULE_RE.captures_iter(STYLESHEET) {
+        let selectors: Vec<&str> = captures
+            .get(1)
+            .unwrap()
+            .as_str()
+            .split(',')
+            .map(str::trim)
+            .collect();
+        if !selectors.iter().all(|sel| CLASS_RE.is_match(sel)) {
+            continue;
+        }
+
+        let declarations: Vec<&str> = captures
+            .get(2)
+            .unwrap()
+            .as_str()
+            .split(';')
+            .map(str::trim)
+            .filter(|decl| !decl.is_empty())
+            .collect();
+        for sel in selectors {
+            let style = styles.entry(&sel[1..]).or_default();
+            for decl in &declarations {
+                if !style.is_empty() {
+                    style.push(' ');
+                }
+                style.push_str(decl);
+                style.push(';');
+            }
+        }
+    }
+
+    styles
+}
+
+fn pygments_class(class: &str) -> Option<&'static str> {
+    Some(match class {
+        "keyword1" => "k",
+        "keyword2" | "quasi_keyword" | "improper" => "kp",
+        "keyword3" => "kr",
+        "literal" => "kc",
+        "tfree" | "tvar" => "kt",
+        "binding" => "nf",
+        "free" | "bound" => "nv",
+        "skolem" => "vi",
+        "var" => "vg",
+        "dynamic_fact" => "nl",
+        "operator" => "o",
+        "inner_numeral" => "m",
+        "inner_quoted" | "inner_string" | "inner_cartouche" | "string" | "alt_string"
+        | "verbatim" | "cartouche" => "s",
+        "antiquoted" | "antiquote" => "si",
+        "comment" | "comment1" | "comment2" | "comment3" => "c",
+        "raw_text" | "plain_text" => "x",
+        _ => return None,
+    })
+}
+
+fn highlightjs_class(class: &str) -> Option<&'static str> {
+    Some(match class {
+        "keyword1" | "keyword3" => "hljs-keyword",
+        "keyword2" | "quasi_keyword" | "improper" => "hljs-built_in",
+        "literal" => "hljs-literal",
+        "tfree" | "tvar" => "hljs-type",
+        "binding" => "hljs-title",
+        "free" | "bound" | "skolem" | "var" => "hljs-variable",
+        "dynamic_fact" => "hljs-symbol",
+        "operator" => "hljs-operator",
+        "inner_numeral" => "hljs-number",
+        "inner_quoted" | "inner_string" | "inner_cartouche" | "string" | "alt_string"
+        | "verbatim" | "cartouche" => "hljs-string",
+        "antiquoted" | "antiquote" => "hljs-subst",
+        "comment" | "comment1" | "comment2" | "comment3" => "hljs-comment",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn translate_drops_unknown() {
+        assert_eq!(ClassStyle::Pygments.translate("keyword1"), Some("k"));
+        assert_eq!(ClassStyle::Pygments.translate("command"), None);
+        assert_eq!(ClassStyle::Isabelle.translate("command"), Some("command"));
+        assert_eq!(ClassStyle::Highlightjs.translate("command"), None);
+    }
+
+    #[test]
+    fn inline_styles() {
+        assert_eq!(
+            StyleMode::Inline.attr("keyword1 command"),
+            r#" style="color: #006699; font-weight: bold;""#
+        );
+        assert_eq!(
+            StyleMode::Inline.attr("cartouche"),
+            r#" style="color: #000000; background-color: #f3f3f3;""#
+        );
+        assert_eq!(StyleMode::Inline.attr("xml_elem"), "");
+        assert_eq!(StyleMode::Classes.attr("command"), r#" class="command""#);
+    }
+}
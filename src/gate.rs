@@ -0,0 +1,144 @@
+//! Conditions under which a render fails, for CI to hold the published theories to a
+//! policy, like not containing any `sorry`. The pages are still written, so that the
+//! problems can be looked at in them.
+
+use serde::{Serialize, Serializer};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// Something whose presence in a theory fails the render, with `--fail-on`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// A proof left out with `sorry`.
+    Sorry,
+    /// An error message from Isabelle.
+    Error,
+    /// A symbol that isn't in the symbol table, which is shown the way it is typed.
+    UnknownSymbol,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sorry" => Ok(Condition::Sorry),
+            "error" => Ok(Condition::Error),
+            "unknown-symbol" => Ok(Condition::UnknownSymbol),
+            _ => Err(format!("unknown condition: {}", s)),
+        }
+    }
+}
+
+impl Condition {
+    /// The name of the condition, as accepted by `FromStr`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Condition::Sorry => "sorry",
+            Condition::Error => "error",
+            Condition::UnknownSymbol => "unknown-symbol",
+        }
+    }
+}
+
+/// The conditions given to `--fail-on`, separated by commas.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Conditions(pub Vec<Condition>);
+
+impl FromStr for Conditions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|condition| condition.trim().parse())
+            .collect::<Result<_, _>>()
+            .map(Conditions)
+    }
+}
+
+// Written the way it is given, so that the manifest can pass it on to `--fail-on`.
+impl Serialize for Conditions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names: Vec<&str> = self.0.iter().map(|condition| condition.name()).collect();
+        serializer.serialize_str(&names.join(","))
+    }
+}
+
+/// What was found in a theory that the conditions are checked against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Findings {
+    pub sorries: usize,
+    pub errors: usize,
+    /// Warnings and legacy warnings.
+    pub warnings: usize,
+    pub unknown_symbols: BTreeSet<String>,
+}
+
+/// Describe each way in which the findings fail the given limits.
+pub fn failures(
+    findings: &Findings,
+    max_warnings: Option<usize>,
+    fail_on: &[Condition],
+) -> Vec<String> {
+    let mut failures = vec![];
+    if let Some(max) = max_warnings.filter(|&max| findings.warnings > max) {
+        failures.push(format!(
+            "{} warnings, more than the {} allowed",
+            findings.warnings, max
+        ));
+    }
+    for condition in fail_on {
+        let failure = match condition {
+            Condition::Sorry if findings.sorries > 0 => {
+                format!("{} uses of sorry", findings.sorries)
+            }
+            Condition::Error if findings.errors > 0 => {
+                format!("{} errors", findings.errors)
+            }
+            Condition::UnknownSymbol if !findings.unknown_symbols.is_empty() => {
+                let symbols: Vec<&str> = findings
+                    .unknown_symbols
+                    .iter()
+                    .map(String::as_str)
+                    .collect();
+                format!("unknown symbols: {}", symbols.join(", "))
+            }
+            _ => continue,
+        };
+        failures.push(failure);
+    }
+    failures
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checks_findings() {
+        let conditions: Conditions = "sorry, unknown-symbol".parse().unwrap();
+        assert_eq!(conditions.0, [Condition::Sorry, Condition::UnknownSymbol]);
+        assert_eq!(
+            serde_json::to_value(&conditions).unwrap(),
+            "sorry,unknown-symbol"
+        );
+        assert!("sorry,typo".parse::<Conditions>().is_err());
+
+        let findings = Findings {
+            sorries: 2,
+            errors: 1,
+            warnings: 3,
+            unknown_symbols: ["\\<foo>".to_owned()].into(),
+        };
+        assert_eq!(
+            failures(&findings, Some(2), &conditions.0),
+            [
+                "3 warnings, more than the 2 allowed",
+                "2 uses of sorry",
+                "unknown symbols: \\<foo>",
+            ]
+        );
+        assert!(failures(&findings, Some(3), &[]).is_empty());
+        assert!(failures(&Findings::default(), Some(0), &conditions.0).is_empty());
+    }
+}
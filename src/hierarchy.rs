@@ -0,0 +1,253 @@
+//! Pages showing how the locales and type classes of a development build on each
+//! other, as far as can be told from the entity markup in the dumps.
+//!
+//! The relations come from the commands that introduce them: `locale` and `class`
+//! definitions that extend others, `sublocale`, `subclass` and `instance` for two
+//! classes. Commands that only name one of the two sides, because the other is the
+//! surrounding `context`, are not taken into account.
+
+use isabelle_markup::markup;
+use isabelle_markup::model::Theory;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, Write};
+
+/// A locale or type class.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Where the entity is defined, if that is known.
+    pub href: Option<String>,
+    /// The names of the entries this one directly builds on.
+    pub parents: BTreeSet<String>,
+}
+
+/// The locales and type classes of the theories added so far, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hierarchy {
+    pub locales: BTreeMap<String, Entry>,
+    pub classes: BTreeMap<String, Entry>,
+}
+
+/// The kind of entities each command relates, and whether it defines the first of
+/// them.
+fn relation(command: &str) -> Option<(&'static str, bool)> {
+    match command {
+        "locale" => Some(("locale", true)),
+        "sublocale" => Some(("locale", false)),
+        "class" => Some(("class", true)),
+        "subclass" | "instance" => Some(("class", false)),
+        _ => None,
+    }
+}
+
+impl Hierarchy {
+    /// Add the locales and classes of a theory, which is rendered to `page`.
+    pub fn add_theory(&mut self, theory: &Theory<'_>, page: &str) {
+        for command in &theory.commands {
+            let (kind, defines) = match relation(command.name) {
+                Some(relation) => relation,
+                None => continue,
+            };
+            let mut entities = markup::entities(&command.markup);
+            entities.retain(|entity| entity.kind == kind);
+            if entities.len() < 2 && !defines {
+                continue;
+            }
+
+            let entries = match kind {
+                "locale" => &mut self.locales,
+                _ => &mut self.classes,
+            };
+            for entity in &entities {
+                let entry = entries.entry(entity.name.to_owned()).or_default();
+                // Prefer the definition itself over references to it.
+                if entry.href.is_none() || entity.def.is_some() {
+                    entry.href =
+                        crate::entity_href_from(entity, page).or(entry.href.take());
+                }
+            }
+
+            let (child, parents) = match entities.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            if defines && child.def.is_none() {
+                continue;
+            }
+            let entry = entries.get_mut(child.name).unwrap();
+            entry.parents.extend(
+                parents
+                    .iter()
+                    .filter(|parent| parent.name != child.name)
+                    .map(|parent| parent.name.to_owned()),
+            );
+        }
+    }
+}
+
+/// Write the entries as nested lists, with each entry below those it builds on.
+fn write_tree(w: &mut impl Write, entries: &BTreeMap<String, Entry>) -> io::Result<()> {
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, entry) in entries {
+        for parent in &entry.parents {
+            children.entry(parent).or_default().push(name);
+        }
+    }
+
+    fn write_entry<'e>(
+        w: &mut impl Write,
+        name: &'e str,
+        entries: &BTreeMap<String, Entry>,
+        children: &BTreeMap<&str, Vec<&'e str>>,
+        path: &mut Vec<&'e str>,
+        written: &mut HashSet<&'e str>,
+    ) -> io::Result<()> {
+        written.insert(name);
+        let text = html_escape::encode_text(name);
+        match &entries[name].href {
+            Some(href) => write!(
+                w,
+                r#"<li><a href="{}">{}</a>"#,
+                html_escape::encode_double_quoted_attribute(href),
+                text
+            )?,
+            None => write!(w, "<li>{}", text)?,
+        }
+
+        // Sublocales may be mutual, so stop at entries already on the way here.
+        let below: Vec<&str> = children
+            .get(name)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|child| !path.contains(child))
+            .collect();
+        if !below.is_empty() {
+            path.push(name);
+            write!(w, "<ul>")?;
+            for child in below {
+                write_entry(w, child, entries, children, path, written)?;
+            }
+            write!(w, "</ul>")?;
+            path.pop();
+        }
+        write!(w, "</li>")
+    }
+
+    let mut written = HashSet::new();
+    write!(w, "<ul>")?;
+    let roots = entries
+        .iter()
+        .filter(|(_, entry)| entry.parents.is_empty())
+        .map(|(name, _)| name.as_str());
+    for name in roots {
+        write_entry(w, name, entries, &children, &mut vec![], &mut written)?;
+    }
+    // Entries that only build on each other have no root to be written below.
+    for name in entries.keys() {
+        if !written.contains(name.as_str()) {
+            write_entry(w, name, entries, &children, &mut vec![], &mut written)?;
+        }
+    }
+    write!(w, "</ul>")
+}
+
+/// Write a page with the locale and class hierarchies.
+pub fn write_page(w: &mut impl Write, hierarchy: &Hierarchy) -> io::Result<()> {
+    crate::write_index_preamble(w, "Locales and classes", "hierarchy")?;
+    for (title, entries) in [
+        ("Locales", &hierarchy.locales),
+        ("Type classes", &hierarchy.classes),
+    ] {
+        if !entries.is_empty() {
+            write!(w, "<h2>{}</h2>", title)?;
+            write_tree(w, entries)?;
+        }
+    }
+    write!(w, "</body>")?;
+    write!(w, "</html>")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entity(kind: &str, name: &str, attr: &str) -> String {
+        format!(
+            "\x05\x06entity\x06kind={}\x06name={}\x06{}\x05{}\x05\x06\x05",
+            kind, name, attr, name
+        )
+    }
+
+    fn command(name: &str, body: &str) -> String {
+        format!(
+            "\x05\x06command_span\x06name={}\x05{} {}\x05\x06\x05\n",
+            name, name, body
+        )
+    }
+
+    #[test]
+    fn locales_and_classes() {
+        let yxml = [
+            command("locale", &entity("locale", "semigroup", "def=1")),
+            command(
+                "locale",
+                &format!(
+                    "{} = {}",
+                    entity("locale", "monoid", "def=2"),
+                    entity("locale", "semigroup", "ref=1")
+                ),
+            ),
+            command(
+                "sublocale",
+                &format!(
+                    "{} \\<subseteq> {}",
+                    entity("locale", "semigroup", "ref=1"),
+                    entity("locale", "monoid", "ref=2")
+                ),
+            ),
+            // The locale being interpreted is the surrounding context.
+            command("sublocale", &entity("locale", "semigroup", "ref=1")),
+            command(
+                "instance",
+                &format!(
+                    "{} \\<subseteq> {}",
+                    entity("class", "linorder", "ref=3\x06def_file=~~/Orderings.thy"),
+                    entity("class", "order", "ref=4\x06def_file=~~/Orderings.thy")
+                ),
+            ),
+        ]
+        .concat();
+        let nodes = yxml::parse(&yxml).unwrap();
+        let mut hierarchy = Hierarchy::default();
+        hierarchy.add_theory(&Theory::from_markup(&nodes), "Algebra.html");
+
+        assert_eq!(
+            hierarchy.locales["monoid"],
+            Entry {
+                href: Some("Algebra.html#entity-2".to_owned()),
+                parents: ["semigroup".to_owned()].into(),
+            }
+        );
+        assert_eq!(
+            hierarchy.locales["semigroup"].parents,
+            ["monoid".to_owned()].into()
+        );
+        assert_eq!(
+            hierarchy.classes["linorder"],
+            Entry {
+                href: Some("Orderings.html#entity-3".to_owned()),
+                parents: ["order".to_owned()].into(),
+            }
+        );
+
+        let mut html = vec![];
+        write_page(&mut html, &hierarchy).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains(
+            r#"<h2>Locales</h2><ul><li><a href="Algebra.html#entity-2">monoid</a><ul><li><a href="Algebra.html#entity-1">semigroup</a></li></ul></li></ul>"#
+        ));
+        assert!(html.contains(
+            r#"<li><a href="Orderings.html#entity-4">order</a><ul><li><a href="Orderings.html#entity-3">linorder</a></li></ul></li>"#
+        ));
+    }
+}
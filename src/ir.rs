@@ -14,13 +14,21 @@
 //! - on the entire `f x`: type `b`
 //!
 //! There isn't really a good way of displaying all of these, so, like Isabelle/jEdit,
-//! we only display the innermost tooltips, and ignore the `f x` one.
+//! we only display the innermost tooltips, and ignore the `f x` one. Optionally, the
+//! outer tooltips can be kept as well, nesting the popups, so that the one for `f x`
+//! shows up on the space between `f` and `x`.
 //!
 //! To do this, we need a representation where all the different markup that may produce
 //! a tooltip.
 
-use crate::symbols::render_symbols;
-use std::io;
+use crate::classes::StyleMode;
+use crate::symbols::{
+    render_symbols, symbol_count, symbols_to_unicode, write_ascii, Unprintables,
+};
+use itertools::Itertools;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::rc::Rc;
 use vec_mut_scan::VecGrowScan;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -28,17 +36,74 @@ pub enum Tag {
     SpanClass(String),
     // contains processed HTML
     Tooltip(String),
+    /// Render the symbols within without their tooltips. Doesn't produce any
+    /// element of its own.
+    PlainSymbols,
+    /// A link to the given URL, like from a constant to its definition.
+    Link(String),
+    /// An element with the given ID, for links to point at. Only kept on the first
+    /// line, as IDs must be unique.
+    Anchor(String),
+    /// The range a message from Isabelle is about, like `warning` or `error`, with
+    /// the text of the message. The message belongs to the first line, and the
+    /// other lines are only highlighted.
+    Message {
+        severity: String,
+        text: String,
+    },
+    /// A range between `\<^bsub>` and `\<^esub>`, or `\<^bsup>` and `\<^esup>`,
+    /// written as the given element. The control symbols are kept as the first and
+    /// last children, but aren't shown.
+    Script(&'static str),
+    /// The part of the sources that the children were lowered from, as given by the
+    /// `offset` and `end_offset` of the markup. These count Isabelle symbols starting
+    /// at 1, like in [`Position`](crate::markup::Position). Doesn't produce any
+    /// element of its own.
+    Source(Range<usize>),
+    /// Text in an embedded language, like `ML`, `document` or `inner` for inner
+    /// syntax. Written as a `data-language` attribute.
+    Language(String),
 }
 
+/// The control symbols that start and end a subscript or superscript range, and the
+/// element the range is written as.
+const SCRIPT_RANGES: &[(&str, &str, &str)] = &[
+    ("\\<^bsub>", "\\<^esub>", "sub"),
+    ("\\<^bsup>", "\\<^esup>", "sup"),
+];
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TagTree<'a> {
     Tag {
-        tag: Tag,
+        /// Shared between the lines the tag is split into, as tooltips can be long.
+        tag: Rc<Tag>,
         children: Vec<TagTree<'a>>,
     },
     Text(&'a str),
 }
 
+/// Callbacks for the nodes of the IR, like [`yxml::Visit`] for the markup. By
+/// default, tags recurse into their children and text is ignored.
+pub trait Visit<'a> {
+    fn visit_tag(&mut self, tag: &Tag, children: &[TagTree<'a>]) {
+        let _ = tag;
+        self.visit_nodes(children);
+    }
+
+    fn visit_text(&mut self, text: &'a str) {
+        let _ = text;
+    }
+
+    fn visit_nodes(&mut self, nodes: &[TagTree<'a>]) {
+        for node in nodes {
+            match node {
+                TagTree::Text(text) => self.visit_text(text),
+                TagTree::Tag { tag, children } => self.visit_tag(tag, children),
+            }
+        }
+    }
+}
+
 impl<'a> TagTree<'a> {
     fn is_empty(&self) -> bool {
         match self {
@@ -50,13 +115,32 @@ impl<'a> TagTree<'a> {
     fn split_lines(&self) -> Vec<TagTree<'a>> {
         match self {
             TagTree::Text(s) => s.split('\n').map(TagTree::Text).collect(),
-            TagTree::Tag { tag, children } => split_lines(&children)
-                .into_iter()
-                .map(|line| TagTree::Tag {
-                    tag: tag.clone(),
-                    children: line,
-                })
-                .collect(),
+            // Each line covers its own part of the range, and the line break after it
+            // is left out.
+            TagTree::Tag { tag, children } => match &**tag {
+                Tag::Source(range) => {
+                    let mut start = range.start;
+                    split_lines(children)
+                        .into_iter()
+                        .map(|line| {
+                            let end = (start + source_len(&line)).min(range.end);
+                            let node = TagTree::Tag {
+                                tag: Rc::new(Tag::Source(start..end)),
+                                children: line,
+                            };
+                            start = (end + 1).min(range.end);
+                            node
+                        })
+                        .collect()
+                }
+                _ => split_lines(children)
+                    .into_iter()
+                    .map(|line| TagTree::Tag {
+                        tag: Rc::clone(tag),
+                        children: line,
+                    })
+                    .collect(),
+            },
         }
     }
 }
@@ -77,6 +161,20 @@ pub fn trim_empty(tree: &mut Vec<TagTree<'_>>) {
 pub fn merge_tooltips<'a>(
     tree: &mut Vec<TagTree<'a>>,
     parent_tooltip: Option<&mut String>,
+) -> bool {
+    merge_tooltips_inner(tree, parent_tooltip, false)
+}
+
+/// Like [`merge_tooltips`], but keep the tooltips whose ranges contain others, for
+/// them to be shown as nested popups.
+pub fn merge_nested_tooltips(tree: &mut Vec<TagTree<'_>>) -> bool {
+    merge_tooltips_inner(tree, None, true)
+}
+
+fn merge_tooltips_inner<'a>(
+    tree: &mut Vec<TagTree<'a>>,
+    parent_tooltip: Option<&mut String>,
+    nested: bool,
 ) -> bool {
     if let Some(parent_tooltip) = parent_tooltip {
         // The parent tooltip is only relevant when this is the only child
@@ -86,9 +184,20 @@ pub fn merge_tooltips<'a>(
                     tag,
                     ref mut children,
                 } => {
-                    match tag {
-                        Tag::SpanClass(_) => {
-                            return merge_tooltips(children, Some(parent_tooltip));
+                    match &**tag {
+                        Tag::SpanClass(_)
+                        | Tag::PlainSymbols
+                        | Tag::Link(_)
+                        | Tag::Anchor(_)
+                        | Tag::Message { .. }
+                        | Tag::Script(_)
+                        | Tag::Source(_)
+                        | Tag::Language(_) => {
+                            return merge_tooltips_inner(
+                                children,
+                                Some(parent_tooltip),
+                                nested,
+                            );
                         }
                         Tag::Tooltip(s) => {
                             parent_tooltip.push('\n');
@@ -96,7 +205,11 @@ pub fn merge_tooltips<'a>(
                             // Obtain ownership of the children
                             if let TagTree::Tag { children, .. } = tree.pop().unwrap() {
                                 *tree = children;
-                                return merge_tooltips(tree, Some(parent_tooltip));
+                                return merge_tooltips_inner(
+                                    tree,
+                                    Some(parent_tooltip),
+                                    nested,
+                                );
                             } else {
                                 unreachable!()
                             }
@@ -112,14 +225,14 @@ pub fn merge_tooltips<'a>(
     let mut any_tooltips = false;
     while let Some(mut node) = scan.next() {
         if let TagTree::Tag { tag, children } = &mut *node {
-            let tooltip = if let Tag::Tooltip(ref mut s) = tag {
+            let tooltip = if let Tag::Tooltip(s) = Rc::make_mut(tag) {
                 Some(s)
             } else {
                 None
             };
-            let has_tooltips = merge_tooltips(children, tooltip);
-            if let Tag::Tooltip(_) = tag {
-                if has_tooltips {
+            let has_tooltips = merge_tooltips_inner(children, tooltip, nested);
+            if let Tag::Tooltip(_) = **tag {
+                if has_tooltips && !nested {
                     node.replace_with_many_with(|node| {
                         if let TagTree::Tag { children, .. } = node {
                             children
@@ -139,14 +252,331 @@ pub fn merge_tooltips<'a>(
     any_tooltips
 }
 
+/// Split the text at the control symbols in [`SCRIPT_RANGES`], so that each of them
+/// is a piece of its own.
+fn split_script_symbols(s: &str) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    for (i, _) in s.match_indices("\\<^") {
+        let symbol = SCRIPT_RANGES
+            .iter()
+            .flat_map(|&(open, close, _)| [open, close])
+            .find(|symbol| s[i..].starts_with(symbol));
+        if let Some(symbol) = symbol {
+            if start < i {
+                pieces.push(&s[start..i]);
+            }
+            pieces.push(symbol);
+            start = i + symbol.len();
+        }
+    }
+    if start < s.len() || pieces.is_empty() {
+        pieces.push(&s[start..]);
+    }
+    pieces
+}
+
+/// Wrap the subscript and superscript ranges in [`Tag::Script`]. A range is only
+/// wrapped if it ends among the same siblings it starts in, and is properly nested
+/// with the other ranges. Other control symbols are left as they are.
+pub fn wrap_script_ranges<'a>(tree: &mut Vec<TagTree<'a>>) {
+    // The ranges started so far, with their element and the nodes since the start.
+    let mut open: Vec<(&'static str, Vec<TagTree<'a>>)> = vec![];
+    let mut out = vec![];
+    for mut node in std::mem::take(tree) {
+        let pieces = match &mut node {
+            TagTree::Tag { children, .. } => {
+                wrap_script_ranges(children);
+                vec![node]
+            }
+            TagTree::Text(s) => split_script_symbols(s)
+                .into_iter()
+                .map(TagTree::Text)
+                .collect(),
+        };
+
+        for piece in pieces {
+            let symbol = match piece {
+                TagTree::Text(s) => s,
+                TagTree::Tag { .. } => "",
+            };
+            let closes = SCRIPT_RANGES
+                .iter()
+                .find(|range| range.1 == symbol)
+                .is_some_and(|range| open.last().is_some_and(|last| last.0 == range.2));
+            if let Some(&(_, _, element)) =
+                SCRIPT_RANGES.iter().find(|range| range.0 == symbol)
+            {
+                open.push((element, vec![piece]));
+            } else if closes {
+                let (element, mut children) = open.pop().unwrap();
+                children.push(piece);
+                let node = TagTree::Tag {
+                    tag: Rc::new(Tag::Script(element)),
+                    children,
+                };
+                open.last_mut()
+                    .map_or(&mut out, |last| &mut last.1)
+                    .push(node);
+            } else {
+                open.last_mut()
+                    .map_or(&mut out, |last| &mut last.1)
+                    .push(piece);
+            }
+        }
+    }
+
+    // Ranges that aren't closed are left as they are.
+    for (_, nodes) in open {
+        out.extend(nodes);
+    }
+    *tree = out;
+}
+
+/// Merge adjacent spans with the same classes into one, so that the output doesn't
+/// close a span only to open an identical one. Dumps tend to have separate markup
+/// for each token, even when it is highlighted the same.
+pub fn merge_adjacent_spans(tree: &mut Vec<TagTree<'_>>) {
+    let mut merged: Vec<TagTree<'_>> = Vec::with_capacity(tree.len());
+    for node in std::mem::take(tree) {
+        match (merged.last_mut(), node) {
+            (
+                Some(TagTree::Tag {
+                    tag: last_tag,
+                    children: last_children,
+                }),
+                TagTree::Tag { tag, children },
+            ) if matches!(*tag, Tag::SpanClass(_)) && *last_tag == tag => {
+                last_children.extend(children)
+            }
+            // Tokens with source offsets each get their own range around the span,
+            // so those are merged too, if they follow each other.
+            (
+                Some(TagTree::Tag {
+                    tag: last_tag,
+                    children: last_children,
+                }),
+                TagTree::Tag { tag, children },
+            ) if joined_sources(last_tag, last_children, &tag, &children).is_some() => {
+                let range = joined_sources(last_tag, last_children, &tag, &children);
+                *last_tag = Rc::new(Tag::Source(range.unwrap()));
+                last_children.extend(children)
+            }
+            (_, node) => merged.push(node),
+        }
+    }
+
+    for node in &mut merged {
+        if let TagTree::Tag { children, .. } = node {
+            merge_adjacent_spans(children);
+        }
+    }
+    *tree = merged;
+}
+
+/// The range covered by two adjacent source ranges, if they are contiguous and the
+/// spans where they meet can be merged.
+fn joined_sources(
+    first: &Tag,
+    first_children: &[TagTree<'_>],
+    second: &Tag,
+    second_children: &[TagTree<'_>],
+) -> Option<Range<usize>> {
+    match (first, second) {
+        (Tag::Source(first), Tag::Source(second)) if first.end == second.start => {
+            match (first_children.last(), second_children.first()) {
+                (
+                    Some(TagTree::Tag { tag: last, .. }),
+                    Some(TagTree::Tag { tag: next, .. }),
+                ) if matches!(**last, Tag::SpanClass(_)) && last == next => {
+                    Some(first.start..second.end)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rename the classes of all spans. Classes for which `f` returns `None` are dropped,
+/// and spans left without any classes are replaced by their children.
+pub fn map_classes<F>(tree: &mut Vec<TagTree<'_>>, f: &F)
+where
+    F: Fn(&str) -> Option<&str>,
+{
+    let mut scan = VecGrowScan::new(tree);
+    while let Some(mut node) = scan.next() {
+        let now_empty = match &mut *node {
+            TagTree::Tag { tag, children } => {
+                map_classes(children, f);
+                match Rc::make_mut(tag) {
+                    Tag::SpanClass(cls) => {
+                        *cls = cls.split_whitespace().filter_map(f).join(" ");
+                        cls.is_empty()
+                    }
+                    Tag::Tooltip(_)
+                    | Tag::PlainSymbols
+                    | Tag::Link(_)
+                    | Tag::Anchor(_)
+                    | Tag::Message { .. }
+                    | Tag::Script(_)
+                    | Tag::Source(_)
+                    | Tag::Language(_) => false,
+                }
+            }
+            TagTree::Text(_) => false,
+        };
+
+        if now_empty {
+            node.replace_with_many_with(|node| {
+                if let TagTree::Tag { children, .. } = node {
+                    children
+                } else {
+                    unreachable!()
+                }
+            });
+        }
+    }
+}
+
+/// Drop the classes of spans that an enclosing span already has, and unwrap spans
+/// left without any classes. This relies on the styles of the classes being
+/// inherited, which holds for the bundled stylesheet.
+pub fn drop_redundant_classes(tree: &mut Vec<TagTree<'_>>) {
+    drop_redundant_inner(tree, &mut vec![]);
+}
+
+fn drop_redundant_inner(tree: &mut Vec<TagTree<'_>>, enclosing: &mut Vec<String>) {
+    let mut scan = VecGrowScan::new(tree);
+    while let Some(mut node) = scan.next() {
+        let now_empty = match &mut *node {
+            TagTree::Tag { tag, children } => match Rc::make_mut(tag) {
+                Tag::SpanClass(cls) => {
+                    *cls = cls
+                        .split_whitespace()
+                        .filter(|class| !enclosing.iter().any(|outer| outer == class))
+                        .join(" ");
+                    let depth = enclosing.len();
+                    enclosing.extend(cls.split_whitespace().map(str::to_owned));
+                    drop_redundant_inner(children, enclosing);
+                    enclosing.truncate(depth);
+                    cls.is_empty()
+                }
+                _ => {
+                    drop_redundant_inner(children, enclosing);
+                    false
+                }
+            },
+            TagTree::Text(_) => false,
+        };
+
+        if now_empty {
+            node.replace_with_many_with(|node| {
+                if let TagTree::Tag { children, .. } = node {
+                    children
+                } else {
+                    unreachable!()
+                }
+            });
+        }
+    }
+}
+
+/// Change the targets of links. Links for which `f` returns `None` are left alone.
+/// Returns whether any link was changed.
+pub fn map_links<F>(tree: &mut [TagTree<'_>], f: &F) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut changed = false;
+    for node in tree {
+        if let TagTree::Tag { tag, children } = node {
+            if let Tag::Link(href) = &**tag {
+                // The tag may be shared with other lines, which keep their own.
+                if let Some(new) = f(href) {
+                    *tag = Rc::new(Tag::Link(new));
+                    changed = true;
+                }
+            }
+            changed |= map_links(children, f);
+        }
+    }
+    changed
+}
+
+/// The severity and text of all the messages in the tree.
+pub fn messages(tree: &[TagTree<'_>]) -> Vec<(String, String)> {
+    struct Messages(Vec<(String, String)>);
+
+    impl<'a> Visit<'a> for Messages {
+        fn visit_tag(&mut self, tag: &Tag, children: &[TagTree<'a>]) {
+            if let Tag::Message { severity, text } = tag {
+                self.0.push((severity.clone(), text.clone()));
+            }
+            self.visit_nodes(children);
+        }
+    }
+
+    let mut messages = Messages(vec![]);
+    messages.visit_nodes(tree);
+    messages.0
+}
+
+/// The IDs of all the anchors in the tree.
+pub fn anchor_ids(tree: &[TagTree<'_>]) -> Vec<String> {
+    struct Anchors(Vec<String>);
+
+    impl<'a> Visit<'a> for Anchors {
+        fn visit_tag(&mut self, tag: &Tag, children: &[TagTree<'a>]) {
+            if let Tag::Anchor(id) = tag {
+                self.0.push(id.clone());
+            }
+            self.visit_nodes(children);
+        }
+    }
+
+    let mut anchors = Anchors(vec![]);
+    anchors.visit_nodes(tree);
+    anchors.0
+}
+
 pub fn split_lines<'a>(input: &[TagTree<'a>]) -> Vec<Vec<TagTree<'a>>> {
     let mut lines = vec![];
     let mut new_children = vec![];
     for child in input {
-        let child_lines = child.split_lines();
+        let child_lines: Vec<Vec<TagTree<'a>>> = match child {
+            TagTree::Tag { tag, children }
+                if matches!(**tag, Tag::Anchor(_) | Tag::Message { .. }) =>
+            {
+                let rest = match &**tag {
+                    Tag::Message { severity, .. } => {
+                        Some(Rc::new(Tag::SpanClass(format!("{}-range", severity))))
+                    }
+                    _ => None,
+                };
+                let mut lines = split_lines(children);
+                for (i, line) in lines.iter_mut().enumerate() {
+                    let tag = match &rest {
+                        _ if i == 0 => tag,
+                        Some(rest) => rest,
+                        None => continue,
+                    };
+                    *line = vec![TagTree::Tag {
+                        tag: Rc::clone(tag),
+                        children: std::mem::take(line),
+                    }];
+                }
+                lines
+            }
+            _ => child
+                .split_lines()
+                .into_iter()
+                .map(|line| vec![line])
+                .collect(),
+        };
         let last_i = child_lines.len() - 1;
         for (i, child_line) in child_lines.into_iter().enumerate() {
-            new_children.push(child_line);
+            new_children.extend(child_line);
             if i != last_i {
                 lines.push(new_children);
                 new_children = vec![];
@@ -158,28 +588,269 @@ pub fn split_lines<'a>(input: &[TagTree<'a>]) -> Vec<Vec<TagTree<'a>>> {
     lines
 }
 
+/// The text covered by the tree, with symbols replaced by their Unicode equivalents.
+pub fn plain_text(input: &[TagTree<'_>]) -> String {
+    let mut text = String::new();
+    for node in input {
+        match node {
+            TagTree::Text(s) => text.push_str(&symbols_to_unicode(s)),
+            TagTree::Tag { children, .. } => text.push_str(&plain_text(children)),
+        }
+    }
+    text
+}
+
+/// The length of the text covered by the tree, in Isabelle symbols.
+pub fn source_len(input: &[TagTree<'_>]) -> usize {
+    input
+        .iter()
+        .map(|node| match node {
+            TagTree::Text(s) => symbol_count(s),
+            TagTree::Tag { children, .. } => source_len(children),
+        })
+        .sum()
+}
+
+/// Settings controlling how the IR is written out as HTML.
+#[derive(Clone, Copy, Debug)]
+pub struct WriteOptions {
+    pub style: StyleMode,
+    /// Emit tooltips as `title` attributes instead of as spans revealed on hover.
+    /// The tooltips must then contain plain text rather than HTML.
+    pub title_tooltips: bool,
+    /// Separate characters that fonts might join into a ligature, like `=>`, with
+    /// zero-width non-joiners.
+    pub break_ligatures: bool,
+    /// How to show control characters and other invisible characters.
+    pub unprintables: Unprintables,
+    /// Also write each symbol the way it is typed, like `\<alpha>`, for the bundled
+    /// script to switch to. The stylesheet hides these until then.
+    pub ascii_toggle: bool,
+    /// Don't write any `data-*` attributes, which HTML sanitizers strip, and use
+    /// classes instead.
+    pub sanitized: bool,
+}
+
+/// The options `render` writes with when none are given.
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            style: StyleMode::Classes,
+            title_tooltips: false,
+            break_ligatures: false,
+            unprintables: Unprintables::Glyph,
+            ascii_toggle: false,
+            sanitized: false,
+        }
+    }
+}
+
+/// An element written for a tag of the IR, as recorded by [`write_nodes_recorded`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrittenSpan {
+    /// The bytes of the output taken up by the element, including its own tags.
+    pub output: Range<usize>,
+    /// The classes of the element, which are empty for links and anchors, as well
+    /// as for anything written with inline styles.
+    pub classes: String,
+    /// The source text within the element, as a byte range of the text covered by
+    /// the nodes written.
+    pub source: Range<usize>,
+}
+
+/// Keeps track of how much was written, to record the spans if asked to.
+struct Recorder<W> {
+    writer: W,
+    written: usize,
+    source: usize,
+    spans: Option<Vec<WrittenSpan>>,
+}
+
+impl<W: io::Write> io::Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
 pub fn write_nodes(
     writer: &mut impl io::Write,
     input: &[TagTree<'_>],
     in_tooltip: bool,
+    opts: WriteOptions,
+) -> io::Result<()> {
+    let mut recorder = Recorder {
+        writer,
+        written: 0,
+        source: 0,
+        spans: None,
+    };
+    write_nodes_inner(&mut recorder, input, !in_tooltip, opts)
+}
+
+/// Like [`write_nodes`], but also return the elements written for the tags of the
+/// IR, in document order.
+pub fn write_nodes_recorded(
+    writer: &mut impl io::Write,
+    input: &[TagTree<'_>],
+    in_tooltip: bool,
+    opts: WriteOptions,
+) -> io::Result<Vec<WrittenSpan>> {
+    let mut recorder = Recorder {
+        writer,
+        written: 0,
+        source: 0,
+        spans: Some(vec![]),
+    };
+    write_nodes_inner(&mut recorder, input, !in_tooltip, opts)?;
+    let mut spans = recorder.spans.unwrap();
+    // Spans are recorded as they end, so the ones inside come first.
+    spans.sort_by_key(|span| span.output.start);
+    Ok(spans)
+}
+
+/// The classes of the element written for `tag`, or `None` if it doesn't get one.
+fn written_classes(tag: &Tag, opts: WriteOptions) -> Option<String> {
+    let classes = match tag {
+        Tag::PlainSymbols | Tag::Source(_) => return None,
+        Tag::Language(name) if opts.sanitized => language_class(name),
+        _ if opts.style == StyleMode::Inline => String::new(),
+        Tag::SpanClass(cls) => cls.clone(),
+        Tag::Tooltip(_) if opts.title_tooltips => String::new(),
+        Tag::Tooltip(_) => "has-tooltip".to_owned(),
+        Tag::Message { severity, .. } => format!("{}-range", severity),
+        Tag::Link(_) | Tag::Anchor(_) | Tag::Script(_) | Tag::Language(_) => {
+            String::new()
+        }
+    };
+    Some(classes)
+}
+
+/// The class marking code in an embedded language, for the sanitized output.
+fn language_class(name: &str) -> String {
+    format!("language-{}", name.to_lowercase())
+}
+
+fn write_nodes_inner<W: io::Write>(
+    writer: &mut Recorder<W>,
+    input: &[TagTree<'_>],
+    symbol_tooltips: bool,
+    opts: WriteOptions,
 ) -> io::Result<()> {
     for node in input {
+        let start = (writer.written, writer.source);
         match node {
-            TagTree::Text(s) => render_symbols(s, &mut *writer, !in_tooltip)?,
-            TagTree::Tag { tag, children } => match tag {
+            TagTree::Text(s) => {
+                render_symbols(s, &mut *writer, symbol_tooltips, opts)?;
+                writer.source += s.len();
+            }
+            TagTree::Tag { tag, children } => match &**tag {
+                Tag::Tooltip(s) if opts.title_tooltips => {
+                    let title = html_escape::encode_double_quoted_attribute(s);
+                    write!(writer, "<span title=\"{}\">", title)?;
+                    write_nodes_inner(writer, children, false, opts)?;
+                    write!(writer, "</span>")?;
+                }
                 Tag::Tooltip(s) => {
-                    assert!(!in_tooltip);
                     write!(writer, "<span class=\"has-tooltip\">")?;
-                    write_nodes(writer, children, true)?;
-                    write!(writer, "<span class=\"tooltip\">{}</span></span>", s)?;
+                    write_nodes_inner(writer, children, false, opts)?;
+                    write!(
+                        writer,
+                        "<span class=\"tooltip markup-tooltip\">{}</span></span>",
+                        s
+                    )?;
                 }
                 Tag::SpanClass(cls) => {
+                    write!(writer, "<span{}>", opts.style.attr(cls))?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                    write!(writer, "</span>")?;
+                }
+                Tag::PlainSymbols => {
+                    write_nodes_inner(writer, children, false, opts)?;
+                }
+                Tag::Source(_) => {
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                }
+                Tag::Link(href) => {
+                    let href = html_escape::encode_double_quoted_attribute(href);
+                    write!(writer, "<a href=\"{}\">", href)?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                    write!(writer, "</a>")?;
+                }
+                Tag::Anchor(id) => {
+                    let id = html_escape::encode_double_quoted_attribute(id);
+                    write!(writer, "<span id=\"{}\">", id)?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                    write!(writer, "</span>")?;
+                }
+                Tag::Language(name) if opts.sanitized => {
+                    let cls = language_class(name);
+                    let cls = html_escape::encode_double_quoted_attribute(&cls);
                     write!(writer, "<span class=\"{}\">", cls)?;
-                    write_nodes(writer, children, in_tooltip)?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                    write!(writer, "</span>")?;
+                }
+                Tag::Language(name) => {
+                    let name = html_escape::encode_double_quoted_attribute(name);
+                    write!(writer, "<span data-language=\"{}\">", name)?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
                     write!(writer, "</span>")?;
                 }
+                Tag::Message { severity, .. } => {
+                    let cls = format!("{}-range", severity);
+                    write!(writer, "<span{}>", opts.style.attr(&cls))?;
+                    write_nodes_inner(writer, children, symbol_tooltips, opts)?;
+                    write!(writer, "</span>")?;
+                }
+                Tag::Script(element) => {
+                    let &(open, close, _) = SCRIPT_RANGES
+                        .iter()
+                        .find(|range| range.2 == *element)
+                        .unwrap();
+                    // After splitting into lines, only the first and last line still
+                    // have the control symbols.
+                    let mut inner = &children[..];
+                    if let Some((TagTree::Text(s), rest)) = inner.split_first() {
+                        if *s == open {
+                            if opts.ascii_toggle {
+                                write_ascii(&mut *writer, s)?;
+                            }
+                            writer.source += s.len();
+                            inner = rest;
+                        }
+                    }
+                    let close_len = match inner.split_last() {
+                        Some((TagTree::Text(s), rest)) if *s == close => {
+                            inner = rest;
+                            s.len()
+                        }
+                        _ => 0,
+                    };
+                    write!(writer, "<{}>", element)?;
+                    write_nodes_inner(writer, inner, symbol_tooltips, opts)?;
+                    write!(writer, "</{}>", element)?;
+                    if opts.ascii_toggle && close_len > 0 {
+                        write_ascii(&mut *writer, close)?;
+                    }
+                    writer.source += close_len;
+                }
             },
         }
+
+        if let (Some(spans), TagTree::Tag { tag, .. }) = (&mut writer.spans, node) {
+            if let Some(classes) = written_classes(tag, opts) {
+                spans.push(WrittenSpan {
+                    output: start.0..writer.written,
+                    classes,
+                    source: start.1..writer.source,
+                });
+            }
+        }
     }
 
     Ok(())
@@ -192,12 +863,12 @@ mod test {
     #[test]
     fn split_lines() {
         let input = TagTree::Tag {
-            tag: Tag::SpanClass("outer".to_owned()),
+            tag: Rc::new(Tag::SpanClass("outer".to_owned())),
             children: vec![
                 TagTree::Text("hi!"),
                 TagTree::Text("one\ntwo"),
                 TagTree::Tag {
-                    tag: Tag::SpanClass("inner".to_owned()),
+                    tag: Rc::new(Tag::SpanClass("inner".to_owned())),
                     children: vec![TagTree::Text("and a half\nthree")],
                 },
             ],
@@ -205,23 +876,23 @@ mod test {
 
         let output = vec![
             TagTree::Tag {
-                tag: Tag::SpanClass("outer".to_owned()),
+                tag: Rc::new(Tag::SpanClass("outer".to_owned())),
                 children: vec![TagTree::Text("hi!"), TagTree::Text("one")],
             },
             TagTree::Tag {
-                tag: Tag::SpanClass("outer".to_owned()),
+                tag: Rc::new(Tag::SpanClass("outer".to_owned())),
                 children: vec![
                     TagTree::Text("two"),
                     TagTree::Tag {
-                        tag: Tag::SpanClass("inner".to_owned()),
+                        tag: Rc::new(Tag::SpanClass("inner".to_owned())),
                         children: vec![TagTree::Text("and a half")],
                     },
                 ],
             },
             TagTree::Tag {
-                tag: Tag::SpanClass("outer".to_owned()),
+                tag: Rc::new(Tag::SpanClass("outer".to_owned())),
                 children: vec![TagTree::Tag {
-                    tag: Tag::SpanClass("inner".to_owned()),
+                    tag: Rc::new(Tag::SpanClass("inner".to_owned())),
                     children: vec![TagTree::Text("three")],
                 }],
             },
@@ -230,12 +901,326 @@ mod test {
         assert_eq!(input.split_lines(), output);
     }
 
+    #[test]
+    fn split_lines_repeats_tooltips() {
+        let tooltip = |children| TagTree::Tag {
+            tag: Rc::new(Tag::Tooltip(":: bool".to_owned())),
+            children,
+        };
+        let input = [tooltip(vec![TagTree::Text("a ∧\n  b")])];
+        assert_eq!(
+            super::split_lines(&input),
+            [
+                vec![tooltip(vec![TagTree::Text("a ∧")])],
+                vec![tooltip(vec![TagTree::Text("  b")])],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_lines_keeps_anchor_on_first_line() {
+        let input = [TagTree::Tag {
+            tag: Rc::new(Tag::Anchor("entity-1".to_owned())),
+            children: vec![TagTree::Text("f\nx")],
+        }];
+        assert_eq!(
+            super::split_lines(&input),
+            [
+                vec![TagTree::Tag {
+                    tag: Rc::new(Tag::Anchor("entity-1".to_owned())),
+                    children: vec![TagTree::Text("f")],
+                }],
+                vec![TagTree::Text("x")],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_lines_keeps_message_on_first_line() {
+        let input = [TagTree::Tag {
+            tag: Rc::new(Tag::Message {
+                severity: "warning".to_owned(),
+                text: "unused".to_owned(),
+            }),
+            children: vec![TagTree::Text("a\nb")],
+        }];
+        let lines = super::split_lines(&input);
+        assert_eq!(
+            messages(&lines[0]),
+            [("warning".to_owned(), "unused".to_owned())]
+        );
+        assert_eq!(messages(&lines[1]), []);
+        assert_eq!(
+            lines[1],
+            [TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("warning-range".to_owned())),
+                children: vec![TagTree::Text("b")],
+            }]
+        );
+    }
+
+    #[test]
+    fn split_lines_splits_source_ranges() {
+        let source = |range, children| TagTree::Tag {
+            tag: Rc::new(Tag::Source(range)),
+            children,
+        };
+        // `\<alpha>` is a single symbol, so the range is 7 symbols long.
+        let input = [source(
+            10..17,
+            vec![TagTree::Text("\\<alpha> x\n"), TagTree::Text("y\nz")],
+        )];
+        assert_eq!(
+            super::split_lines(&input),
+            [
+                vec![source(10..13, vec![TagTree::Text("\\<alpha> x")])],
+                vec![source(14..15, vec![TagTree::Text(""), TagTree::Text("y")])],
+                vec![source(16..17, vec![TagTree::Text("z")])],
+            ]
+        );
+    }
+
+    #[test]
+    fn links_and_anchors() {
+        let link = |href: &str| TagTree::Tag {
+            tag: Rc::new(Tag::Link(href.to_owned())),
+            children: vec![TagTree::Text("f")],
+        };
+        let mut input = vec![
+            TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                children: vec![TagTree::Tag {
+                    tag: Rc::new(Tag::Anchor("entity-1".to_owned())),
+                    children: vec![TagTree::Text("f")],
+                }],
+            },
+            link("#entity-1"),
+            link("Nat.html#entity-2"),
+        ];
+        assert_eq!(anchor_ids(&input), ["entity-1"]);
+
+        let changed = map_links(&mut input, &|href| {
+            href.strip_prefix('#')
+                .map(|id| format!("page-2.html#{}", id))
+        });
+        assert!(changed);
+        assert_eq!(input[1], link("page-2.html#entity-1"));
+        assert_eq!(input[2], link("Nat.html#entity-2"));
+
+        let mut buf = vec![];
+        let opts = WriteOptions {
+            ..WriteOptions::default()
+        };
+        write_nodes(&mut buf, &input, false, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<span class="free"><span id="entity-1">f</span></span>"#.to_owned()
+                + r#"<a href="page-2.html#entity-1">f</a><a href="Nat.html#entity-2">f</a>"#
+        );
+    }
+
+    #[test]
+    fn nested_tooltips() {
+        let tooltip = |s: &str, children| TagTree::Tag {
+            tag: Rc::new(Tag::Tooltip(s.to_owned())),
+            children,
+        };
+        let inner = vec![
+            tooltip("a \u{21d2} b", vec![TagTree::Text("f")]),
+            TagTree::Text(" "),
+            tooltip("a", vec![TagTree::Text("x")]),
+        ];
+        let input = vec![tooltip("b", inner.clone())];
+
+        let mut innermost = input.clone();
+        assert!(merge_tooltips(&mut innermost, None));
+        assert_eq!(innermost, inner);
+
+        let mut nested = input.clone();
+        assert!(merge_nested_tooltips(&mut nested));
+        assert_eq!(nested, input);
+
+        let opts = WriteOptions {
+            title_tooltips: true,
+            ..WriteOptions::default()
+        };
+        let mut buf = vec![];
+        write_nodes(&mut buf, &nested, false, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<span title="b"><span title="a ⇒ b">f</span> <span title="a">x</span></span>"#
+        );
+    }
+
+    #[test]
+    fn records_spans() {
+        let input = [
+            TagTree::Text("by "),
+            TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                children: vec![TagTree::Tag {
+                    tag: Rc::new(Tag::Link("#entity-1".to_owned())),
+                    children: vec![TagTree::Text("x")],
+                }],
+            },
+        ];
+        let opts = WriteOptions {
+            ..WriteOptions::default()
+        };
+        let mut buf = vec![];
+        let spans = write_nodes_recorded(&mut buf, &input, false, opts).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            spans,
+            [
+                WrittenSpan {
+                    output: 3..html.len(),
+                    classes: "free".to_owned(),
+                    source: 3..4,
+                },
+                WrittenSpan {
+                    output: 22..html.len() - 7,
+                    classes: String::new(),
+                    source: 3..4,
+                },
+            ]
+        );
+        assert_eq!(
+            &html[spans[1].output.clone()],
+            r##"<a href="#entity-1">x</a>"##
+        );
+    }
+
+    #[test]
+    fn script_ranges() {
+        let free = TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass("free".to_owned())),
+            children: vec![TagTree::Text("j")],
+        };
+        let mut input = vec![
+            TagTree::Text("x\\<^bsub>i "),
+            free.clone(),
+            TagTree::Text("\\<^esub>\\<^bsup>k"),
+        ];
+        wrap_script_ranges(&mut input);
+        assert_eq!(
+            input,
+            [
+                TagTree::Text("x"),
+                TagTree::Tag {
+                    tag: Rc::new(Tag::Script("sub")),
+                    children: vec![
+                        TagTree::Text("\\<^bsub>"),
+                        TagTree::Text("i "),
+                        free,
+                        TagTree::Text("\\<^esub>"),
+                    ],
+                },
+                TagTree::Text("\\<^bsup>"),
+                TagTree::Text("k"),
+            ]
+        );
+
+        let opts = WriteOptions {
+            ..WriteOptions::default()
+        };
+        let mut buf = vec![];
+        let spans = write_nodes_recorded(&mut buf, &input, true, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"x<sub>i <span class="free">j</span></sub>⇗k"#
+        );
+        assert_eq!(spans[0].source, 1.."x\\<^bsub>i j\\<^esub>".len());
+    }
+
+    #[test]
+    fn merges_adjacent_spans() {
+        let span = |cls: &str, children| TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass(cls.to_owned())),
+            children,
+        };
+        let mut input = vec![
+            span("free", vec![TagTree::Text("x")]),
+            span("free", vec![span("bound", vec![TagTree::Text("y")])]),
+            span("free", vec![span("bound", vec![TagTree::Text("z")])]),
+            TagTree::Text(" "),
+            span("free", vec![TagTree::Text("w")]),
+        ];
+        merge_adjacent_spans(&mut input);
+        assert_eq!(
+            input,
+            [
+                span(
+                    "free",
+                    vec![
+                        TagTree::Text("x"),
+                        span("bound", vec![TagTree::Text("y"), TagTree::Text("z")]),
+                    ]
+                ),
+                TagTree::Text(" "),
+                span("free", vec![TagTree::Text("w")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_classes_unwraps_empty() {
+        let mut input = vec![TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass("keyword1 command".to_owned())),
+            children: vec![TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("command".to_owned())),
+                children: vec![TagTree::Text("lemma")],
+            }],
+        }];
+
+        map_classes(&mut input, &|cls| Some(cls).filter(|&cls| cls != "command"));
+        assert_eq!(
+            input,
+            [TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("keyword1".to_owned())),
+                children: vec![TagTree::Text("lemma")],
+            }]
+        );
+    }
+
+    #[test]
+    fn drops_redundant_classes() {
+        let span = |cls: &str, children| TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass(cls.to_owned())),
+            children,
+        };
+        let mut input = vec![span(
+            "free",
+            vec![
+                span("free", vec![TagTree::Text("x")]),
+                TagTree::Tag {
+                    tag: Rc::new(Tag::Link("#entity-1".to_owned())),
+                    children: vec![span("free bound", vec![TagTree::Text("y")])],
+                },
+            ],
+        )];
+        drop_redundant_classes(&mut input);
+        assert_eq!(
+            input,
+            [span(
+                "free",
+                vec![
+                    TagTree::Text("x"),
+                    TagTree::Tag {
+                        tag: Rc::new(Tag::Link("#entity-1".to_owned())),
+                        children: vec![span("bound", vec![TagTree::Text("y")])],
+                    },
+                ],
+            )]
+        );
+    }
+
     #[test]
     fn merge_tooltips_merges() {
         let mut input = vec![TagTree::Tag {
-            tag: Tag::Tooltip("outer tooltip".to_owned()),
+            tag: Rc::new(Tag::Tooltip("outer tooltip".to_owned())),
             children: vec![TagTree::Tag {
-                tag: Tag::Tooltip("inner tooltip".to_owned()),
+                tag: Rc::new(Tag::Tooltip("inner tooltip".to_owned())),
                 children: vec![TagTree::Text("hi")],
             }],
         }];
@@ -244,7 +1229,7 @@ mod test {
         assert_eq!(
             input,
             [TagTree::Tag {
-                tag: Tag::Tooltip("outer tooltip\ninner tooltip".to_owned()),
+                tag: Rc::new(Tag::Tooltip("outer tooltip\ninner tooltip".to_owned())),
                 children: vec![TagTree::Text("hi")],
             }]
         );
@@ -253,10 +1238,10 @@ mod test {
     #[test]
     fn merge_tooltips_trims() {
         let mut input = vec![TagTree::Tag {
-            tag: Tag::Tooltip("outer tooltip".to_owned()),
+            tag: Rc::new(Tag::Tooltip("outer tooltip".to_owned())),
             children: vec![
                 TagTree::Tag {
-                    tag: Tag::Tooltip("inner tooltip".to_owned()),
+                    tag: Rc::new(Tag::Tooltip("inner tooltip".to_owned())),
                     children: vec![TagTree::Text("hi")],
                 },
                 TagTree::Text("some more text"),
@@ -268,7 +1253,7 @@ mod test {
             input,
             [
                 TagTree::Tag {
-                    tag: Tag::Tooltip("inner tooltip".to_owned()),
+                    tag: Rc::new(Tag::Tooltip("inner tooltip".to_owned())),
                     children: vec![TagTree::Text("hi")],
                 },
                 TagTree::Text("some more text")
@@ -279,11 +1264,11 @@ mod test {
     #[test]
     fn merge_tooltips_merges_across_layers() {
         let mut input = vec![TagTree::Tag {
-            tag: Tag::Tooltip("outer tooltip".to_owned()),
+            tag: Rc::new(Tag::Tooltip("outer tooltip".to_owned())),
             children: vec![TagTree::Tag {
-                tag: Tag::SpanClass("cls".to_owned()),
+                tag: Rc::new(Tag::SpanClass("cls".to_owned())),
                 children: vec![TagTree::Tag {
-                    tag: Tag::Tooltip("inner tooltip".to_owned()),
+                    tag: Rc::new(Tag::Tooltip("inner tooltip".to_owned())),
                     children: vec![TagTree::Text("hi")],
                 }],
             }],
@@ -293,12 +1278,85 @@ mod test {
         assert_eq!(
             input,
             [TagTree::Tag {
-                tag: Tag::Tooltip("outer tooltip\ninner tooltip".to_owned()),
+                tag: Rc::new(Tag::Tooltip("outer tooltip\ninner tooltip".to_owned())),
                 children: vec![TagTree::Tag {
-                    tag: Tag::SpanClass("cls".to_owned()),
+                    tag: Rc::new(Tag::SpanClass("cls".to_owned())),
                     children: vec![TagTree::Text("hi")],
                 }],
             }]
         );
     }
+
+    #[test]
+    fn write_title_tooltips() {
+        let input = vec![TagTree::Tag {
+            tag: Rc::new(Tag::Tooltip(":: \"a\" ⇒ b".to_owned())),
+            children: vec![TagTree::Text("f\\<^sub>1")],
+        }];
+        let opts = WriteOptions {
+            title_tooltips: true,
+            ..WriteOptions::default()
+        };
+
+        let mut buf = Vec::new();
+        write_nodes(&mut buf, &input, false, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<span title=":: &quot;a&quot; ⇒ b">f<sub>1</sub></span>"#
+        );
+        assert_eq!(plain_text(&input), "f⇩1");
+    }
+
+    #[test]
+    fn write_sanitized_language() {
+        let input = vec![TagTree::Tag {
+            tag: Rc::new(Tag::Language("ML".to_owned())),
+            children: vec![TagTree::Text("val x = 1")],
+        }];
+
+        let mut buf = Vec::new();
+        write_nodes(&mut buf, &input, false, WriteOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<span data-language="ML">val x = 1</span>"#
+        );
+
+        let opts = WriteOptions {
+            sanitized: true,
+            ..WriteOptions::default()
+        };
+        let mut buf = Vec::new();
+        write_nodes(&mut buf, &input, false, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<span class="language-ml">val x = 1</span>"#
+        );
+    }
+
+    #[test]
+    fn tooltip_classes() {
+        let input = vec![
+            TagTree::Text("\\<alpha>"),
+            TagTree::Tag {
+                tag: Rc::new(Tag::PlainSymbols),
+                children: vec![TagTree::Text("\\<beta>")],
+            },
+            TagTree::Tag {
+                tag: Rc::new(Tag::Tooltip("x".to_owned())),
+                children: vec![TagTree::Text("y")],
+            },
+        ];
+        let opts = WriteOptions {
+            ..WriteOptions::default()
+        };
+
+        let mut buf = Vec::new();
+        write_nodes(&mut buf, &input, false, opts).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert_eq!(html.matches("tooltip symbol-tooltip").count(), 1);
+        assert!(html.ends_with(
+            "β<span class=\"has-tooltip\">y\
+             <span class=\"tooltip markup-tooltip\">x</span></span>"
+        ));
+    }
 }
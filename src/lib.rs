@@ -0,0 +1,13 @@
+//! Structured access to the markup produced by `isabelle dump`, for tools that need
+//! more than the rendered HTML, along with the lowering of the markup that the HTML is
+//! rendered from, which can be extended with handlers for custom markup.
+
+pub mod assets;
+pub mod classes;
+pub mod ir;
+pub mod lower;
+pub mod markup;
+mod ml_types;
+pub mod model;
+pub mod rules;
+pub mod symbols;
@@ -0,0 +1,174 @@
+//! Checking that the links between generated pages resolve, including the anchors
+//! they point to.
+
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+static LINK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\b(?:href|src)="([^"]*)""#).unwrap());
+static ID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\bid="([^"]*)""#).unwrap());
+
+fn attr_values(re: &Regex, html: &str) -> Vec<String> {
+    re.captures_iter(html)
+        .map(|captures| html_escape::decode_html_entities(&captures[1]).into_owned())
+        .collect()
+}
+
+/// Whether the link points outside of the output, and thus can't be checked.
+fn is_external(link: &str) -> bool {
+    link.contains("://") || link.starts_with("mailto:") || link.starts_with("//")
+}
+
+/// The path with `.gz` added to its name, as for pages written with `--compress`.
+fn compressed(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+fn html_files(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let uncompressed = if is_compressed(&path) {
+            path.with_extension("")
+        } else {
+            path.clone()
+        };
+        if path.is_dir() {
+            html_files(&path, out)?;
+        } else if uncompressed.extension().is_some_and(|ext| ext == "html") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Read a page, decompressing it if its name ends in `.gz`.
+fn read_page(path: &Path) -> io::Result<String> {
+    if !is_compressed(path) {
+        return fs::read_to_string(path);
+    }
+
+    let mut html = String::new();
+    GzDecoder::new(File::open(path)?).read_to_string(&mut html)?;
+    Ok(html)
+}
+
+/// Check the links in all HTML files under `dir`, and return a description of each
+/// broken one. Links to other sites are not checked.
+///
+/// Compressed pages are checked as well, and a link to a page resolves to its
+/// compressed version, which is how the pages written with `--compress` link to each
+/// other.
+pub fn check(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    html_files(dir, &mut files)?;
+
+    // The IDs in each file, loaded on demand.
+    let mut ids: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut broken = vec![];
+    for file in &files {
+        let html = read_page(file)?;
+        for link in attr_values(&LINK_RE, &html) {
+            if is_external(&link) {
+                continue;
+            }
+
+            let (path, fragment) = match link.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (link.as_str(), None),
+            };
+            let mut target = if path.is_empty() {
+                file.clone()
+            } else {
+                file.parent().unwrap_or(dir).join(path)
+            };
+            if !target.exists() && compressed(&target).exists() {
+                target = compressed(&target);
+            }
+
+            if !target.exists() {
+                broken.push(format!("{}: {} doesn't exist", file.display(), link));
+                continue;
+            }
+
+            let fragment = match fragment {
+                Some(fragment) if !fragment.is_empty() => fragment,
+                _ => continue,
+            };
+            if !ids.contains_key(&target) {
+                let target_ids = match read_page(&target) {
+                    Ok(target_html) => {
+                        attr_values(&ID_RE, &target_html).into_iter().collect()
+                    }
+                    Err(_) => HashSet::new(),
+                };
+                ids.insert(target.clone(), target_ids);
+            }
+            if !ids[&target].contains(fragment) {
+                broken.push(format!("{}: no anchor for {}", file.display(), link));
+            }
+        }
+    }
+
+    Ok(broken)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn finds_broken_links() {
+        let dir =
+            std::env::temp_dir().join(format!("check-links-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.html"),
+            r##"<a href="b.html#x"></a><a href="b.html#y"></a><a href="c.html"></a>
+                <a href="https://example.com/"></a><a href="#me"></a><span id="me"></span>"##,
+        )
+        .unwrap();
+        fs::write(dir.join("b.html"), r#"<span id="x"></span>"#).unwrap();
+        // Links between compressed pages name the uncompressed ones.
+        let mut d = GzEncoder::new(
+            File::create(dir.join("d.html.gz")).unwrap(),
+            Default::default(),
+        );
+        d.write_all(br##"<a href="e.html#z"></a><a href="d.html#w"></a>"##)
+            .unwrap();
+        d.finish().unwrap();
+        let mut e = GzEncoder::new(
+            File::create(dir.join("e.html.gz")).unwrap(),
+            Default::default(),
+        );
+        e.write_all(br#"<span id="z"></span>"#).unwrap();
+        e.finish().unwrap();
+
+        let mut broken = check(&dir).unwrap();
+        broken.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        let a = dir.join("a.html");
+        let d = dir.join("d.html.gz");
+        assert_eq!(
+            broken,
+            [
+                format!("{}: c.html doesn't exist", a.display()),
+                format!("{}: no anchor for b.html#y", a.display()),
+                format!("{}: no anchor for d.html#w", d.display()),
+            ]
+        );
+    }
+}
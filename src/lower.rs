@@ -0,0 +1,621 @@
+//! Lowering the markup of a dump to the IR in [`crate::ir`], which decides what each
+//! element is shown as.
+//!
+//! Most elements are looked up in the [`Rules`]. Forks of Isabelle with markup of
+//! their own can also handle it in code, by passing a [`MarkupHandler`].
+
+use crate::classes::ClassStyle;
+use crate::ir::*;
+use crate::markup::{Entity, Position};
+use crate::ml_types;
+use crate::rules::{BodyFormat, Rule, Rules};
+use crate::symbols::symbols_to_unicode;
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use yxml::Node;
+
+/// Custom lowering for some markup elements, tried before the built-in handling.
+pub trait MarkupHandler {
+    /// Lower `node` along with everything in it, or return `None` to leave it to the
+    /// next handler, and eventually the built-in handling. The children can be
+    /// lowered as usual with [`lower_node`], passing `opts` along, as well as any
+    /// error.
+    fn lower<'a>(
+        &self,
+        node: &Node<'a>,
+        opts: LowerOptions<'_>,
+    ) -> Result<Option<Vec<TagTree<'a>>>, UnknownMarkup>;
+}
+
+/// An `xml_elem` of a kind that isn't in the rules, found while lowering with
+/// [`LowerOptions::strict`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownMarkup {
+    /// The `xml_name` of the element.
+    pub xml_name: String,
+    /// The line the element is on, as given by the innermost markup around it that
+    /// has a position.
+    pub line: Option<usize>,
+    /// Like `line`, for markup that only gives the offset in the sources.
+    pub offset: Option<usize>,
+}
+
+impl UnknownMarkup {
+    /// Take the position from the attributes of an element around the unknown one,
+    /// unless one was already found closer to it.
+    fn within(mut self, attrs: &yxml::Attributes<'_>) -> UnknownMarkup {
+        if let (None, None, Some(position)) =
+            (self.line, self.offset, Position::from_attrs(attrs))
+        {
+            self.line = position.line;
+            self.offset = position.offset;
+        }
+        self
+    }
+}
+
+impl fmt::Display for UnknownMarkup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown xml_elem kind {:?}", self.xml_name)?;
+        match (self.line, self.offset) {
+            (Some(line), _) => write!(f, " on line {}", line),
+            (None, Some(offset)) => write!(f, " at offset {}", offset),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for UnknownMarkup {}
+
+impl From<UnknownMarkup> for io::Error {
+    fn from(err: UnknownMarkup) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+/// Settings controlling how the markup is lowered to the IR.
+#[derive(Clone, Copy)]
+pub struct LowerOptions<'h> {
+    /// Whether to build tooltips for the markup. When disabled, only the classes
+    /// used for highlighting are kept.
+    pub tooltips: bool,
+    /// Whether tooltips should contain plain text instead of HTML.
+    pub plain_tooltips: bool,
+    /// Whether to show how to type each symbol on hover.
+    pub symbol_tooltips: bool,
+    /// Whether to keep tooltips that contain other tooltips, instead of only the
+    /// innermost ones.
+    pub nested_tooltips: bool,
+    /// Whether to fail with an [`UnknownMarkup`] error on `xml_elem` kinds that
+    /// aren't in the rules, rather than showing their body in a tooltip after their
+    /// raw name.
+    pub strict: bool,
+    /// Whether to drop the classes that an enclosing span already has.
+    pub collapse_classes: bool,
+    pub class_style: ClassStyle,
+    /// What each markup element is shown as.
+    pub rules: &'h Rules,
+    /// Handlers for custom markup, tried in order before the rules.
+    pub handlers: &'h [&'h dyn MarkupHandler],
+}
+
+/// The options `render` lowers with when none are given, with the bundled rules and
+/// no handlers.
+impl Default for LowerOptions<'_> {
+    fn default() -> Self {
+        LowerOptions {
+            tooltips: true,
+            plain_tooltips: false,
+            symbol_tooltips: true,
+            nested_tooltips: false,
+            strict: false,
+            collapse_classes: false,
+            class_style: ClassStyle::Isabelle,
+            rules: Rules::bundled(),
+            handlers: &[],
+        }
+    }
+}
+
+/// Lower the markup, and simplify the result as the options say.
+pub fn processed_ir<'a>(
+    input: &[Node<'a>],
+    opts: LowerOptions<'_>,
+) -> Result<Vec<TagTree<'a>>, UnknownMarkup> {
+    let mut ir = lower_nodes(input, opts)?;
+    trim_empty(&mut ir);
+    wrap_script_ranges(&mut ir);
+    if opts.tooltips && opts.nested_tooltips {
+        merge_nested_tooltips(&mut ir);
+    } else if opts.tooltips {
+        merge_tooltips(&mut ir, None);
+    }
+    if opts.class_style != ClassStyle::Isabelle {
+        map_classes(&mut ir, &|cls| opts.class_style.translate(cls));
+    }
+    if opts.collapse_classes {
+        drop_redundant_classes(&mut ir);
+    }
+    merge_adjacent_spans(&mut ir);
+    Ok(ir)
+}
+
+/// Render markup for a tooltip, as HTML or plain text as the options say.
+pub fn render_to_string(
+    input: &[Node<'_>],
+    opts: LowerOptions<'_>,
+) -> Result<String, UnknownMarkup> {
+    let ir = processed_ir(input, opts)?;
+    if opts.plain_tooltips {
+        return Ok(plain_text(&ir));
+    }
+
+    let mut buf = Vec::new();
+    write_nodes(
+        &mut io::Cursor::new(&mut buf),
+        &ir,
+        false,
+        WriteOptions::default(),
+    )
+    .unwrap();
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+/// Text to put in a tooltip, escaped unless the tooltips are plain text.
+fn tooltip_text<'t>(text: &'t str, opts: LowerOptions<'_>) -> Cow<'t, str> {
+    if opts.plain_tooltips {
+        Cow::Borrowed(text)
+    } else {
+        html_escape::encode_text(text)
+    }
+}
+
+/// Lower a single node, along with everything in it.
+pub fn lower_node<'input>(
+    node: &Node<'input>,
+    opts: LowerOptions<'_>,
+) -> Result<Vec<TagTree<'input>>, UnknownMarkup> {
+    for handler in opts.handlers {
+        if let Some(lowered) = handler.lower(node, opts)? {
+            return Ok(lowered);
+        }
+    }
+
+    match node {
+        Node::Text(s) if !opts.symbol_tooltips => Ok(vec![TagTree::Tag {
+            tag: Rc::new(Tag::PlainSymbols),
+            children: vec![TagTree::Text(s)],
+        }]),
+        Node::Text(s) => Ok(vec![TagTree::Text(s)]),
+        Node::Tag {
+            name,
+            attrs,
+            children,
+        } => {
+            lower_tag(node, name, attrs, children, opts).map_err(|err| err.within(attrs))
+        }
+    }
+}
+
+/// Lower the nodes one after the other, into a single list.
+fn lower_nodes<'input>(
+    nodes: &[Node<'input>],
+    opts: LowerOptions<'_>,
+) -> Result<Vec<TagTree<'input>>, UnknownMarkup> {
+    let mut lowered = vec![];
+    for node in nodes {
+        lowered.extend(lower_node(node, opts)?);
+    }
+    Ok(lowered)
+}
+
+/// Lower an element of the markup, which has the given parts.
+fn lower_tag<'input>(
+    node: &Node<'input>,
+    name: &str,
+    attrs: &yxml::Attributes<'input>,
+    children: &[Node<'input>],
+    opts: LowerOptions<'_>,
+) -> Result<Vec<TagTree<'input>>, UnknownMarkup> {
+    let rule = opts.rules.find(name, attrs);
+    if rule.is_some_and(|rule| rule.ignore) {
+        return Ok(vec![]);
+    }
+    let class = rule.and_then(|rule| rule.classes(attrs));
+    let language = rule
+        .and_then(|rule| rule.language_attr.as_deref())
+        .and_then(|key| attrs.get(key));
+
+    // Messages, with the message as the body, like the types of terms.
+    let message_body = rule
+        .and_then(|rule| rule.message.as_ref())
+        .zip(node.children_named("xml_body").next());
+    let message = match message_body {
+        Some((severity, body)) => Some(Tag::Message {
+            severity: severity.clone(),
+            text: plain_text(&processed_ir(body.children(), opts)?),
+        }),
+        None => None,
+    };
+
+    let body_tooltip = |prefix: &str| -> Result<Option<String>, UnknownMarkup> {
+        let body = match node.children_named("xml_body").next() {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        let format = rule.map_or(BodyFormat::Markup, |rule| rule.body_format);
+        let is_text = body
+            .children()
+            .iter()
+            .all(|child| matches!(child, Node::Text(_)));
+        let body = match format {
+            BodyFormat::MlType if is_text => {
+                let ty = symbols_to_unicode(&body.text_content());
+                let ty = ml_types::layout(&ty, ml_types::WIDTH);
+                tooltip_text(&ty, opts).into_owned()
+            }
+            _ => render_to_string(body.children(), opts)?,
+        };
+        Ok(Some(format!("{}{}", prefix, body)))
+    };
+    let tooltip = match rule {
+        _ if !opts.tooltips || message.is_some() => None,
+        Some(Rule {
+            tooltip: Some(tooltip),
+            ..
+        }) => Some(tooltip_text(tooltip, opts).into_owned()),
+        Some(Rule {
+            body_tooltip: Some(prefix),
+            name_type_variable,
+            ..
+        }) => {
+            let prefix = tooltip_text(prefix, opts);
+            // The element may be on the type variable, or on the annotation
+            // after it.
+            let is_type_variable = node
+                .to_source_text()
+                .trim_start_matches('?')
+                .starts_with('\'');
+            if *name_type_variable && is_type_variable {
+                let opts = LowerOptions {
+                    tooltips: false,
+                    ..opts
+                };
+                let variable = render_to_string(children, opts)?;
+                body_tooltip(&format!("{} {}", variable, prefix))?
+            } else {
+                body_tooltip(&prefix)?
+            }
+        }
+        Some(_) => None,
+        None if name == "xml_elem" && opts.strict => {
+            return Err(UnknownMarkup {
+                xml_name: node.attr("xml_name").unwrap_or_default().to_owned(),
+                line: None,
+                offset: None,
+            });
+        }
+        // Newer versions of Isabelle may add kinds we don't know about.
+        None if name == "xml_elem" => {
+            let kind = node.attr("xml_name").unwrap_or("xml_elem");
+            body_tooltip(&format!("{}: ", tooltip_text(kind, opts)))?
+        }
+        None => None,
+    };
+    let mut children = lower_nodes(children, opts)?;
+
+    if let Some(s) = tooltip {
+        children = vec![TagTree::Tag {
+            tag: Rc::new(Tag::Tooltip(s.to_string())),
+            children,
+        }];
+    }
+
+    if let Some(s) = class {
+        children = vec![TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass(s)),
+            children,
+        }];
+    }
+
+    if let Some(tag) = message {
+        children = vec![TagTree::Tag {
+            tag: Rc::new(tag),
+            children,
+        }];
+    }
+
+    if let Some(entity) = Entity::from_node(node) {
+        let tag = match (entity.def, entity_href(&entity)) {
+            (Some(def), _) => Some(Tag::Anchor(entity_anchor(def))),
+            (None, href) => href.map(Tag::Link),
+        };
+        if let Some(tag) = tag {
+            children = vec![TagTree::Tag {
+                tag: Rc::new(tag),
+                children,
+            }];
+        }
+    }
+
+    if let Some(name) = language {
+        children = vec![TagTree::Tag {
+            tag: Rc::new(Tag::Language(name.to_owned())),
+            children,
+        }];
+    }
+
+    if let Some(Position {
+        offset: Some(offset),
+        end_offset: Some(end_offset),
+        ..
+    }) = Position::from_attrs(attrs)
+    {
+        children = vec![TagTree::Tag {
+            tag: Rc::new(Tag::Source(offset..end_offset)),
+            children,
+        }];
+    }
+
+    Ok(children)
+}
+
+/// The ID of the element at the definition of an entity.
+pub fn entity_anchor(def: i64) -> String {
+    format!("entity-{}", def)
+}
+
+/// Where a reference to an entity should link to. Entities from other theories are
+/// expected to be rendered alongside this one, named after their theory files.
+pub fn entity_href(entity: &Entity<'_>) -> Option<String> {
+    let anchor = entity_anchor(entity.reference?);
+    match entity.def_file {
+        Some(file) => {
+            let stem = Path::new(file).file_stem()?.to_string_lossy();
+            Some(format!("{}.html#{}", stem, anchor))
+        }
+        None => Some(format!("#{}", anchor)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Highlights `highlight` elements with the class given as an attribute.
+    struct Highlight;
+
+    impl MarkupHandler for Highlight {
+        fn lower<'a>(
+            &self,
+            node: &Node<'a>,
+            opts: LowerOptions<'_>,
+        ) -> Result<Option<Vec<TagTree<'a>>>, UnknownMarkup> {
+            let (class, children) = match node {
+                Node::Tag {
+                    name: "highlight",
+                    children,
+                    ..
+                } => match node.attr("class") {
+                    Some(class) => (class, children),
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+            let mut lowered = vec![];
+            for child in children {
+                lowered.extend(lower_node(child, opts)?);
+            }
+            Ok(Some(vec![TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass(class.to_owned())),
+                children: lowered,
+            }]))
+        }
+    }
+
+    #[test]
+    fn custom_handler() {
+        let nodes = yxml::parse(
+            "\x05\x06highlight\x06class=fancy\x05\x05\x06free\x05x\x05\x06\x05\x05\x06\x05\
+             \x05\x06highlight\x05y\x05\x06\x05",
+        )
+        .unwrap();
+        let opts = LowerOptions {
+            tooltips: false,
+            handlers: &[&Highlight],
+            ..LowerOptions::default()
+        };
+        assert_eq!(
+            processed_ir(&nodes, opts).unwrap(),
+            [
+                TagTree::Tag {
+                    tag: Rc::new(Tag::SpanClass("fancy".to_owned())),
+                    children: vec![TagTree::Tag {
+                        tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                        children: vec![TagTree::Text("x")],
+                    }],
+                },
+                // Without a class, the element is left to the built-in handling.
+                TagTree::Text("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_source_offsets() {
+        let nodes =
+            yxml::parse("\x05\x06free\x06offset=4\x06end_offset=5\x05x\x05\x06\x05")
+                .unwrap();
+        let opts = LowerOptions {
+            tooltips: false,
+            ..LowerOptions::default()
+        };
+        assert_eq!(
+            processed_ir(&nodes, opts).unwrap(),
+            [TagTree::Tag {
+                tag: Rc::new(Tag::Source(4..5)),
+                children: vec![TagTree::Tag {
+                    tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                    children: vec![TagTree::Text("x")],
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn merges_spans_with_offsets() {
+        let nodes = yxml::parse(
+            "\x05\x06free\x06offset=1\x06end_offset=2\x05x\x05\x06\x05\
+             \x05\x06free\x06offset=2\x06end_offset=3\x05y\x05\x06\x05 \
+             \x05\x06free\x06offset=4\x06end_offset=5\x05z\x05\x06\x05",
+        )
+        .unwrap();
+        let opts = LowerOptions {
+            tooltips: false,
+            ..LowerOptions::default()
+        };
+        let free = |children| TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass("free".to_owned())),
+            children,
+        };
+        assert_eq!(
+            processed_ir(&nodes, opts).unwrap(),
+            [
+                TagTree::Tag {
+                    tag: Rc::new(Tag::Source(1..3)),
+                    children: vec![free(vec![TagTree::Text("x"), TagTree::Text("y")])],
+                },
+                TagTree::Text(" "),
+                TagTree::Tag {
+                    tag: Rc::new(Tag::Source(4..5)),
+                    children: vec![free(vec![TagTree::Text("z")])],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_languages() {
+        let nodes = yxml::parse(
+            "\x05\x06language\x06name=ML\x06symbols=true\x05\
+             \x05\x06keyword1\x06kind=command\x05val\x05\x06\x05 x = 1\x05\x06\x05",
+        )
+        .unwrap();
+        let opts = LowerOptions {
+            tooltips: false,
+            ..LowerOptions::default()
+        };
+        assert_eq!(
+            processed_ir(&nodes, opts).unwrap(),
+            [TagTree::Tag {
+                tag: Rc::new(Tag::Language("ML".to_owned())),
+                children: vec![
+                    TagTree::Tag {
+                        tag: Rc::new(Tag::SpanClass("keyword1 command".to_owned())),
+                        children: vec![TagTree::Text("val")],
+                    },
+                    TagTree::Text(" x = 1"),
+                ],
+            }]
+        );
+    }
+
+    /// The elements and attributes of HTML written for `--sanitized`, as pairs of
+    /// the element and each of its attributes, or an empty one if it has none.
+    fn elements(html: &str) -> Vec<(&str, &str)> {
+        let mut elements = vec![];
+        for tag in html.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').unwrap()];
+            if tag.starts_with('/') {
+                continue;
+            }
+            let (name, mut rest) = tag.split_once(' ').unwrap_or((tag, ""));
+            elements.push((name, ""));
+            while let Some((attr, after)) = rest.split_once("=\"") {
+                elements.push((name, attr.trim()));
+                rest = &after[after.find('"').unwrap() + 1..];
+            }
+        }
+        elements
+    }
+
+    #[test]
+    fn sanitized_allowlist() {
+        let nodes = yxml::parse(
+            "\x05\x06entity\x06def=1\x06kind=constant\x06name=f\x05f\x05\x06\x05 \
+             \x05\x06entity\x06ref=1\x06kind=constant\x06name=f\x05f\x05\x06\x05 \
+             \x05\x06xml_elem\x06xml_name=typing\x05\
+             \x05\x06xml_body\x05\x05\x06tfree\x05'a\x05\x06\x05\x05\x06\x05\
+             \x05\x06free\x05x\x05\x06\x05\x05\x06\x05 \\<longrightarrow> x\\<^sub>1 \
+             x\\<^bsub>i\\<^esub> \
+             \x05\x06xml_elem\x06xml_name=warning\x05\
+             \x05\x06xml_body\x05bad\x05\x06\x05y\x05\x06\x05 \
+             \x05\x06language\x06name=ML\x05val\x05\x06\x05",
+        )
+        .unwrap();
+        let opts = LowerOptions {
+            plain_tooltips: true,
+            ..LowerOptions::default()
+        };
+        let write_opts = WriteOptions {
+            title_tooltips: true,
+            sanitized: true,
+            ..WriteOptions::default()
+        };
+        let ir = processed_ir(&nodes, opts).unwrap();
+        let mut buf = Vec::new();
+        write_nodes(&mut buf, &ir, false, write_opts).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+
+        let allowed = ["span", "a", "sub", "sup"];
+        let allowed_attrs = ["", "class", "title", "href", "id"];
+        let elements = elements(&html);
+        for &(element, attr) in &elements {
+            assert!(allowed.contains(&element), "<{}> in {}", element, html);
+            assert!(allowed_attrs.contains(&attr), "{} in {}", attr, html);
+        }
+        for expected in [("span", "title"), ("a", "href"), ("sub", "")] {
+            assert!(
+                elements.contains(&expected),
+                "no {:?} in {}",
+                expected,
+                html
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_xml_elem() {
+        let nodes = yxml::parse(
+            "\x05\x06position\x06line=3\x06offset=10\x05\
+             \x05\x06xml_elem\x06xml_name=fancy\x05\
+             \x05\x06xml_body\x05int\x05\x06\x05x\x05\x06\x05\x05\x06\x05",
+        )
+        .unwrap();
+        assert_eq!(
+            processed_ir(&nodes, LowerOptions::default()).unwrap(),
+            [TagTree::Tag {
+                tag: Rc::new(Tag::Tooltip("fancy: int".to_owned())),
+                children: vec![TagTree::Text("x")],
+            }]
+        );
+
+        let opts = LowerOptions {
+            strict: true,
+            ..LowerOptions::default()
+        };
+        let err = processed_ir(&nodes, opts).unwrap_err();
+        assert_eq!(
+            err,
+            UnknownMarkup {
+                xml_name: "fancy".to_owned(),
+                line: Some(3),
+                offset: Some(10),
+            }
+        );
+        assert_eq!(err.to_string(), "unknown xml_elem kind \"fancy\" on line 3");
+    }
+}
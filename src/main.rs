@@ -1,154 +1,1478 @@
 use argh::FromArgs;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use isabelle_markup::assets;
+use isabelle_markup::classes::{ClassStyle, StyleMode};
+use isabelle_markup::ir::*;
+use isabelle_markup::lower::*;
+use isabelle_markup::markup::Entity;
+use isabelle_markup::model::{redact_proofs, Heading, Theory};
+use isabelle_markup::rules::Rules;
+use isabelle_markup::symbols::{self, Unprintables};
+use itertools::Itertools;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{self, prelude::*, BufWriter};
-use std::path::PathBuf;
-use yxml::Node;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use yxml::{ErrorKind, Node};
 
-mod ir;
-mod symbols;
-
-use ir::*;
+mod abstracts;
+mod annotations;
+mod gate;
+mod hierarchy;
+mod links;
+mod man;
+mod manifest;
+mod normalize;
+mod notation;
+mod spans;
+mod tokens;
 
 #[derive(FromArgs)]
 /// Convert output of 'isabelle dump' to HTML.
 struct Options {
+    #[argh(subcommand)]
+    command: Command,
+}
+
+// Only ever constructed once, and argh can't parse into a box.
+#[allow(clippy::large_enum_variant)]
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Render(RenderOptions),
+    Check(CheckOptions),
+    Diff(DiffOptions),
+    Stats(StatsOptions),
+    Tokens(TokensOptions),
+    Tree(TreeOptions),
+    CheckLinks(CheckLinksOptions),
+    Hierarchy(HierarchyOptions),
+    Notation(NotationOptions),
+    AuditSymbols(AuditSymbolsOptions),
+    Man(ManOptions),
+}
+
+// The options are serialized for the manifest, by the names of their flags.
+#[derive(FromArgs, Serialize)]
+#[argh(subcommand, name = "render")]
+#[serde(rename_all = "kebab-case")]
+/// Render a dump to HTML.
+struct RenderOptions {
     #[argh(positional)]
+    #[serde(skip)]
     /// path to dump
     dump_path: PathBuf,
 
     #[argh(positional)]
+    #[serde(skip)]
     /// output path
     out_path: PathBuf,
+
+    #[argh(switch)]
+    /// only highlight the source, without building any tooltips
+    fast: bool,
+
+    #[argh(option, default = "ClassStyle::Isabelle")]
+    /// naming scheme for the emitted classes: isabelle (default), pygments or
+    /// highlightjs
+    class_style: ClassStyle,
+
+    #[argh(switch)]
+    /// emit inline style attributes instead of classes, for targets that strip
+    /// stylesheets; implies --fast
+    inline_styles: bool,
+
+    #[argh(switch)]
+    /// only use markup commonly allowed by HTML sanitizers, with tooltips as title
+    /// attributes
+    sanitized: bool,
+
+    #[argh(switch)]
+    /// refuse to emit anything a strict content security policy would block, i.e.
+    /// inline styles or scripts
+    strict_csp: bool,
+
+    #[argh(switch)]
+    /// add a subresource integrity hash of the bundled stylesheet to its link
+    sri: bool,
+
+    #[argh(switch)]
+    /// render malformed or truncated dumps as well as possible, with a warning for
+    /// each problem found
+    lossy: bool,
+
+    #[argh(switch)]
+    /// start the output with a YAML front matter block describing the theory, for
+    /// static site generators
+    front_matter: bool,
+
+    #[argh(option)]
+    /// JSON file with notes to show next to lines or theorems
+    annotations: Option<PathBuf>,
+
+    #[argh(option)]
+    /// name of a theorem whose proof should be replaced with a placeholder, as in
+    /// exercise sheets; can be given multiple times
+    redact: Vec<String>,
+
+    #[argh(switch)]
+    /// lay out each command in a table row, with the annotations for it in a
+    /// second column
+    side_by_side: bool,
+
+    #[argh(switch)]
+    /// start each page with a table of contents, listing the headings of the theory
+    toc: bool,
+
+    #[argh(option)]
+    /// an abstract to show above the theory: an HTML file, a LaTeX file like the
+    /// document/root.tex of an AFP entry, or plain text
+    r#abstract: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// fold the proofs, so that they are only shown when clicked
+    fold_proofs: bool,
+
+    #[argh(switch)]
+    /// add an anchor for each command, named after the theorem or derived from
+    /// the command's text
+    anchors: bool,
+
+    #[argh(option)]
+    /// split the output into several pages of about this many bytes each, linked
+    /// to each other; pages are only split between commands
+    max_page_size: Option<usize>,
+
+    #[argh(switch)]
+    /// with --max-page-size, load the following pages as the reader scrolls down,
+    /// using the bundled script
+    lazy: bool,
+
+    #[argh(option)]
+    /// put lines longer than this many characters in a box of their own that
+    /// scrolls sideways, so that they don't widen the whole page; the bundled
+    /// script adds a button for showing them in full
+    max_line_length: Option<usize>,
+
+    #[argh(switch)]
+    /// bring the text to Unicode normalization form C
+    nfc: bool,
+
+    #[argh(switch)]
+    /// replace codepoints that are easily confused with more common ones, like
+    /// non-breaking hyphens or the micro sign
+    replace_confusables: bool,
+
+    #[argh(switch)]
+    /// put zero-width non-joiners between characters like => or :: that coding
+    /// fonts may show as a single glyph; these are copied along with the text
+    break_ligatures: bool,
+
+    #[argh(option, default = "Unprintables::Glyph")]
+    /// how to show control characters and other invisible characters: glyph
+    /// (default) for a visible replacement, escape for an escape sequence, or raw
+    unprintables: Unprintables,
+
+    #[argh(switch)]
+    /// also write each symbol the way it is typed, like \<alpha>, with a button
+    /// for switching to that added by the bundled script
+    ascii_toggle: bool,
+
+    #[argh(switch)]
+    /// add a button to each command for copying it the way it is typed, with
+    /// symbols like \<alpha>, using the bundled script
+    copy_buttons: bool,
+
+    #[argh(switch)]
+    /// don't show how to type each symbol on hover, keeping the tooltips for
+    /// the markup
+    no_symbol_tooltips: bool,
+
+    #[argh(switch)]
+    /// keep the tooltips of markup that contains other markup with tooltips, like
+    /// the type of a whole application, shown when hovering outside the inner ones
+    nested_tooltips: bool,
+
+    #[argh(switch)]
+    /// leave out classes that an enclosing span already has, for a smaller output
+    /// and simpler DOM, unless the stylesheet relies on nesting
+    collapse_classes: bool,
+
+    #[argh(option)]
+    /// a JSON file with rules for what markup is shown as, tried before the
+    /// bundled ones
+    markup_rules: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// fail on markup this tool doesn't know about, like new kinds of types shown
+    /// on hover, naming it and where it is, instead of rendering it generically
+    strict: bool,
+
+    #[argh(switch)]
+    /// only write the code itself, without the surrounding HTML document, for
+    /// embedding into another page that includes the stylesheet
+    fragment: bool,
+
+    #[argh(option)]
+    /// also write where each element for the markup ended up in the pages, as JSON,
+    /// for tools that check the output
+    spans: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// compress the pages with gzip, adding .gz to their names; implied by an
+    /// output path ending in .gz
+    compress: bool,
+
+    #[argh(option)]
+    /// fail after writing the pages if the theory has more than this many warnings
+    max_warnings: Option<usize>,
+
+    #[argh(option)]
+    /// fail after writing the pages if the theory has any of these, separated by
+    /// commas: sorry, error, or unknown-symbol for symbols not in the symbol table
+    fail_on: Option<gate::Conditions>,
+
+    #[argh(option)]
+    #[serde(skip)]
+    /// also write a manifest of the pages, recording all the options they were
+    /// rendered with, as JSON
+    manifest: Option<PathBuf>,
+
+    #[argh(option)]
+    #[serde(skip)]
+    /// render with the options recorded in this manifest, to reproduce an earlier
+    /// build; can't be combined with other options, apart from --manifest
+    same_as: Option<PathBuf>,
 }
 
-fn processed_ir<'a>(input: &[Node<'a>]) -> Vec<TagTree<'a>> {
-    let mut ir: Vec<TagTree> = input.iter().flat_map(lower_node).collect();
-    trim_empty(&mut ir);
-    merge_tooltips(&mut ir, None);
-    ir
-}
-
-fn render_to_string(input: &[Node<'_>]) -> String {
-    let ir = processed_ir(input);
-    let mut buf = Vec::new();
-    write_nodes(&mut io::Cursor::new(&mut buf), &ir, false).unwrap();
-    String::from_utf8(buf).unwrap()
-}
-
-fn lower_node<'input>(node: &Node<'input>) -> Vec<TagTree<'input>> {
-    match node {
-        Node::Text(s) => vec![TagTree::Text(s)],
-        Node::Tag {
-            name,
-            attrs,
-            children,
-        } => {
-            let class = match *name {
-                // Ignore xml_body for now - this tag is part of the mechanism that
-                // provides type information on hover.
-                "xml_body" => return vec![],
-                "keyword1" | "keyword2" | "keyword3" => {
-                    let mut classes = name.to_string();
-                    if let Some(kind) = attrs.get("kind") {
-                        classes.push(' ');
-                        classes.push_str(kind);
-                    }
-                    Some(classes)
-                }
-                "binding" | "tfree" | "tvar" | "free" | "skolem" | "bound" | "var"
-                | "literal" | "inner_numeral" | "inner_quoted" | "inner_cartouche"
-                | "inner_string" | "antiquoted" | "comment1" | "comment2"
-                | "comment3" | "dynamic_fact" | "quasi_keyword" | "operator"
-                | "string" | "alt_string" | "verbatim" | "cartouche" | "comment"
-                | "improper" | "antiquote" | "raw_text" | "plain_text" => {
-                    Some(name.to_string())
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check")]
+/// Check that a dump can be rendered, without writing any output.
+struct CheckOptions {
+    #[argh(positional)]
+    /// path to dump
+    dump_path: PathBuf,
+
+    #[argh(switch)]
+    /// also fail on markup this tool doesn't know about, which would be rendered
+    /// generically, as with render --strict
+    strict: bool,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "diff")]
+/// List how the markup changed between two dumps of a theory, like from two
+/// revisions of it.
+struct DiffOptions {
+    #[argh(positional)]
+    /// path to the old dump
+    old_path: PathBuf,
+
+    #[argh(positional)]
+    /// path to the new dump
+    new_path: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "stats")]
+/// Print the numbers of lines, lemmas, sorries and warnings of a theory, as shown
+/// on index pages.
+struct StatsOptions {
+    #[argh(positional)]
+    /// path to dump
+    dump_path: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "tokens")]
+/// Export the highlighting as a JSON token stream.
+struct TokensOptions {
+    #[argh(positional)]
+    /// path to dump
+    dump_path: PathBuf,
+
+    #[argh(positional)]
+    /// output path, standard output if omitted
+    out_path: Option<PathBuf>,
+
+    #[argh(option, default = "tokens::Format::Json")]
+    /// the format to write: json (default) for the classes of each token, or lsp
+    /// for the semantic tokens of the Language Server Protocol
+    format: tokens::Format,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "tree")]
+/// Print the markup of a dump as an indented outline, for debugging.
+struct TreeOptions {
+    #[argh(positional)]
+    /// path to dump
+    dump_path: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check-links")]
+/// Check that the links between the generated pages in a directory resolve.
+struct CheckLinksOptions {
+    #[argh(positional)]
+    /// directory with the generated pages
+    dir: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "hierarchy")]
+/// Write a page with the locale and class hierarchies of a set of theories.
+struct HierarchyOptions {
+    #[argh(option)]
+    /// output path, next to the rendered theories
+    out: PathBuf,
+
+    #[argh(positional)]
+    /// paths to the dumps, named after their theories
+    dump_paths: Vec<PathBuf>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "notation")]
+/// Write a page with the notation declared in a set of theories. Links to the
+/// declarations expect the theories to be rendered with --anchors.
+struct NotationOptions {
+    #[argh(option)]
+    /// output path, next to the rendered theories
+    out: PathBuf,
+
+    #[argh(positional)]
+    /// paths to the dumps, named after their theories
+    dump_paths: Vec<PathBuf>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "audit-symbols")]
+/// Compare the bundled symbol table with the one in an Isabelle distribution.
+struct AuditSymbolsOptions {
+    #[argh(positional)]
+    /// root directory of the Isabelle distribution
+    isabelle_home: PathBuf,
+
+    #[argh(option)]
+    /// replace the symbol table at this path, like src/symbols in a checkout of
+    /// this tool, with the distribution's
+    update: Option<PathBuf>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "man")]
+/// Print a man page for this tool, made from the help of each subcommand.
+struct ManOptions {}
+
+/// Where an occurrence of an entity in the theory rendered to `page` points to, for
+/// linking to it from another page. Unlike [`entity_href`], definitions point to
+/// themselves.
+fn entity_href_from(entity: &Entity<'_>, page: &str) -> Option<String> {
+    let href = entity_href(entity)
+        .or_else(|| entity.def.map(|def| format!("#{}", entity_anchor(def))))?;
+    if href.starts_with('#') {
+        Some(format!("{}{}", page, href))
+    } else {
+        Some(href)
+    }
+}
+
+/// Parse the dump, along with the error that cut it short, if any. That can only
+/// happen with `lossy`, as otherwise any error is returned.
+fn parse_dump<'a>(
+    yxml: &'a [u8],
+    buf: &'a mut String,
+    lossy: bool,
+) -> io::Result<(Vec<Node<'a>>, Option<String>)> {
+    if !lossy {
+        return yxml::parse_bytes(yxml, buf)
+            .map(|nodes| (nodes, None))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+    }
+
+    let input = match std::str::from_utf8(yxml) {
+        Ok(input) => input,
+        Err(_) => {
+            *buf = String::from_utf8_lossy(yxml).into_owned();
+            buf
+        }
+    };
+    let (nodes, errors) = yxml::parse_lossy(input);
+    let mut truncation = None;
+    for error in errors {
+        match error.kind {
+            // Whatever comes after these is missing from the tree.
+            ErrorKind::NoClosingX | ErrorKind::UnclosedTag(_) if truncation.is_none() => {
+                eprintln!("error: rendering truncated due to error: {}", error);
+                truncation = Some(error.to_string());
+            }
+            _ => eprintln!("warning: {}", error),
+        }
+    }
+    Ok((nodes, truncation))
+}
+
+/// Numbers that give an overview of a theory, for index pages.
+struct Stats {
+    lines: usize,
+    lemmas: usize,
+    sorries: usize,
+    warnings: usize,
+}
+
+impl Stats {
+    fn new(lines: &[Vec<TagTree<'_>>], theory: &Theory) -> Self {
+        Stats {
+            lines: lines.len(),
+            lemmas: theory
+                .commands
+                .iter()
+                .filter(|cmd| cmd.states_theorem())
+                .count(),
+            sorries: theory
+                .commands
+                .iter()
+                .filter(|cmd| cmd.name == "sorry")
+                .count(),
+            warnings: lines
+                .iter()
+                .flat_map(|line| messages(line))
+                .filter(|(severity, _)| severity == "warning" || severity == "legacy")
+                .count(),
+        }
+    }
+
+    /// Write the counts as YAML, with each line indented by `indent`.
+    fn write_counts(&self, w: &mut impl Write, indent: &str) -> io::Result<()> {
+        writeln!(w, "{}lines: {}", indent, self.lines)?;
+        writeln!(w, "{}lemmas: {}", indent, self.lemmas)?;
+        writeln!(w, "{}sorries: {}", indent, self.sorries)?;
+        writeln!(w, "{}warnings: {}", indent, self.warnings)
+    }
+}
+
+/// Write the theory name, imports and theorem names, along with some statistics
+/// and all the options used, as YAML. Values are written as JSON, which is valid YAML.
+fn write_front_matter(
+    w: &mut impl Write,
+    nodes: &[Node<'_>],
+    stats: &Stats,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    let theory = Theory::from_markup(nodes);
+    writeln!(w, "---")?;
+    if let Some(header) = theory.header() {
+        writeln!(w, "theory: {}", serde_json::to_string(&header.name)?)?;
+        writeln!(w, "imports: {}", serde_json::to_string(&header.imports)?)?;
+    }
+    writeln!(
+        w,
+        "theorems: {}",
+        serde_json::to_string(&theory.theorem_names())?
+    )?;
+    writeln!(w, "stats:")?;
+    stats.write_counts(w, "  ")?;
+    // The same options as in the manifest, by the names of their flags.
+    writeln!(w, "options:")?;
+    for (name, value) in recorded_options(options)? {
+        writeln!(w, "  {}: {}", name, value)?;
+    }
+    writeln!(w, "---")
+}
+
+fn write_anchors(w: &mut impl Write, anchors: Option<&Vec<String>>) -> io::Result<()> {
+    for anchor in anchors.into_iter().flatten() {
+        write!(
+            w,
+            r#"<span id="{}"></span>"#,
+            html_escape::encode_double_quoted_attribute(anchor)
+        )?;
+    }
+    Ok(())
+}
+
+fn render(options: RenderOptions) -> io::Result<()> {
+    if let Some(path) = &options.same_as {
+        return render(options_same_as(path, &options)?);
+    }
+
+    let started = Instant::now();
+    if options.inline_styles && options.class_style != ClassStyle::Isabelle {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "inline styles are only available for the isabelle class style",
+        ));
+    }
+
+    if options.inline_styles && options.ascii_toggle {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the ASCII toggle relies on the stylesheet to hide one of the two forms, so \
+             it can't be used with inline styles",
+        ));
+    }
+
+    if options.inline_styles && options.copy_buttons {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the copy buttons are styled by the stylesheet, so they can't be used with \
+             inline styles",
+        ));
+    }
+
+    if options.inline_styles && options.strict_csp {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "inline styles can't be used with a strict content security policy",
+        ));
+    }
+
+    if options.fold_proofs && options.strict_csp {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "folded proofs use inline styles to keep the line numbers right, so they \
+             can't be used with a strict content security policy",
+        ));
+    }
+
+    if options.sanitized {
+        // These need elements, attributes or scripts that HTML sanitizers strip.
+        let unsanitized = [
+            ("--inline-styles", options.inline_styles),
+            ("--copy-buttons", options.copy_buttons),
+            ("--fold-proofs", options.fold_proofs),
+            ("--ascii-toggle", options.ascii_toggle),
+            ("--lazy", options.lazy),
+        ];
+        if let Some((flag, _)) = unsanitized.iter().find(|(_, set)| *set) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} relies on markup that HTML sanitizers strip, so it can't be \
+                     used with --sanitized",
+                    flag
+                ),
+            ));
+        }
+    }
+
+    if options.fold_proofs && options.side_by_side {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "proofs can't be folded in the side-by-side layout",
+        ));
+    }
+
+    let mut yxml = std::fs::read(&options.dump_path)?;
+    if options.nfc || options.replace_confusables {
+        // Invalid UTF-8 is left for `parse_dump` to report, or replace with --lossy.
+        let input = match std::str::from_utf8(&yxml) {
+            Ok(input) => Some(Cow::Borrowed(input)),
+            Err(_) if options.lossy => Some(String::from_utf8_lossy(&yxml)),
+            Err(_) => None,
+        };
+        if let Some(input) = input {
+            yxml = normalize::normalize(&input, options.nfc, options.replace_confusables)
+                .into_bytes();
+        }
+    }
+    let mut buf = String::new();
+    let (mut nodes, truncation) = parse_dump(&yxml, &mut buf, options.lossy)?;
+    if !options.redact.is_empty() {
+        nodes = redact_proofs(&nodes, &options.redact);
+    }
+    let fast = options.fast || options.inline_styles;
+    let style = if options.inline_styles {
+        StyleMode::Inline
+    } else {
+        StyleMode::Classes
+    };
+    let loaded_rules;
+    let rules = match &options.markup_rules {
+        Some(path) => {
+            loaded_rules = Rules::load(path)?;
+            &loaded_rules
+        }
+        None => Rules::bundled(),
+    };
+    let opts = LowerOptions {
+        tooltips: !fast,
+        plain_tooltips: options.sanitized,
+        symbol_tooltips: !options.no_symbol_tooltips,
+        nested_tooltips: options.nested_tooltips,
+        strict: options.strict,
+        collapse_classes: options.collapse_classes,
+        class_style: options.class_style,
+        rules,
+        ..LowerOptions::default()
+    };
+    let write_opts = WriteOptions {
+        style,
+        title_tooltips: options.sanitized,
+        break_ligatures: options.break_ligatures,
+        unprintables: options.unprintables,
+        ascii_toggle: options.ascii_toggle,
+        sanitized: options.sanitized,
+    };
+    let ir = processed_ir(&nodes, opts)?;
+    let mut lines = split_lines(&ir);
+    let theory = Theory::from_markup(&nodes);
+
+    // The line each entity of this theory is defined on. References to them link
+    // within the page, even if they name a file, as that file may be this one.
+    let mut definitions = HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        definitions.extend(anchor_ids(line).into_iter().map(|id| (id, i)));
+    }
+    for line in &mut lines {
+        map_links(line, &|href| {
+            let (_, id) = href.split_once('#')?;
+            definitions.get(id).map(|_| format!("#{}", id))
+        });
+    }
+
+    let abstract_html = match &options.r#abstract {
+        Some(path) => Some(abstracts::load(path)?),
+        None => None,
+    };
+    let annotations = match &options.annotations {
+        Some(path) => annotations::load(path, &theory)?,
+        None => Default::default(),
+    };
+
+    // The anchors to put at the start of each line.
+    let mut anchors: HashMap<usize, Vec<String>> = HashMap::new();
+    if options.anchors {
+        for (cmd, anchor) in theory.commands.iter().zip(theory.anchors()) {
+            anchors.entry(cmd.line - 1).or_default().push(anchor);
+        }
+    }
+
+    // The headings, by the line they start on.
+    let headings: HashMap<usize, Heading> = theory
+        .headings()
+        .into_iter()
+        .map(|heading| (heading.line - 1, heading))
+        .collect();
+
+    let pre = match options.class_style.container_class() {
+        Some(cls) => format!(r#"<pre class="isabelle-code {}">"#, cls),
+        None => format!("<pre{}>", style.attr("isabelle-code")),
+    };
+
+    // Each command starts a new group of lines, along with any blank lines after it.
+    // In the side-by-side layout, each group is a row of the table, and pages are
+    // only ever split between groups.
+    let mut starts: Vec<usize> = theory.commands.iter().map(|cmd| cmd.line - 1).collect();
+    starts.retain(|&start| start != 0);
+    starts.dedup();
+    starts.insert(0, 0);
+    starts.push(lines.len());
+
+    // Renders the lines of a group, along with the spans written for each line, if
+    // they are needed.
+    let render_group = |lines: &[Vec<TagTree>], group: &[usize]| {
+        let mut out = vec![];
+        let mut spans = vec![];
+        if options.side_by_side {
+            write!(out, "<tr><td>{}", pre)?;
+        }
+
+        for (i, line) in lines.iter().enumerate().take(group[1]).skip(group[0]) {
+            let long = options
+                .max_line_length
+                .is_some_and(|max| plain_text(line).chars().count() > max);
+            if long {
+                write!(out, "<span{}>", style.attr("long-line"))?;
+            }
+            let heading = headings.get(&i);
+            if let Some(heading) = heading {
+                write!(
+                    out,
+                    r#"<h{} id="{}"{}>"#,
+                    heading.level,
+                    html_escape::encode_double_quoted_attribute(&heading.id),
+                    style.attr("heading")
+                )?;
+            }
+            if options.copy_buttons && i == group[0] {
+                // Where the lines of each command start, for the copy buttons.
+                write!(out, "<code data-command>")?;
+            } else {
+                write!(out, "<code>")?;
+            }
+            write_anchors(&mut out, anchors.get(&i))?;
+            // Without tooltips, symbols are rendered the same way as inside a tooltip.
+            if options.spans.is_some() {
+                let start = out.len();
+                for mut span in write_nodes_recorded(&mut out, line, fast, write_opts)? {
+                    span.output = span.output.start + start..span.output.end + start;
+                    spans.push((i + 1, span));
                 }
-                _ => None,
-            };
-
-            let tooltip = match *name {
-                "citation" => Some("citation".to_owned()),
-                "token_range" => Some("inner syntax token".to_owned()),
-                "free" => Some("free variable".to_owned()),
-                "skolem" => Some("skolem variable".to_owned()),
-                "bound" => Some("bound variable".to_owned()),
-                "var" => Some("schematic variable".to_owned()),
-                "tfree" => Some("free type variable".to_owned()),
-                "tvar" => Some("schematic type variable".to_owned()),
-                "xml_elem" => {
-                    let prefix = match attrs["xml_name"] {
-                        "ML_typing" => "ML: ",
-                        "typing" | "sorting" => ":: ",
-                        "class_parameter" => "",
-                        name => unimplemented!("{}", name),
-                    };
-
-                    let body = children
-                        .iter()
-                        .find_map(|child| match child {
-                            Node::Tag {
-                                name: "xml_body",
-                                children,
-                                ..
-                            } => Some(children),
-                            _ => None,
-                        })
-                        .unwrap();
-                    Some(format!("{}{}", prefix, render_to_string(body)))
+            } else {
+                write_nodes(&mut out, line, fast, write_opts)?;
+            }
+            if !options.side_by_side {
+                for note in annotations.get(&i).into_iter().flatten() {
+                    write!(
+                        out,
+                        "<span{}>{}</span>",
+                        style.attr("annotation"),
+                        html_escape::encode_text(note)
+                    )?;
                 }
-                _ => None,
-            };
+            }
+            write!(out, "</code>")?;
+            if let Some(heading) = heading {
+                write!(out, "</h{}>", heading.level)?;
+            }
+            if long {
+                write!(out, "</span>")?;
+            }
+        }
+
+        // The messages about the command, collapsed below it.
+        for (severity, text) in lines[group[0]..group[1]].iter().flat_map(|l| messages(l))
+        {
+            write!(
+                out,
+                "<details{}><summary>{}</summary>{}</details>",
+                style.attr(&format!("message {}-message", severity)),
+                severity,
+                html_escape::encode_text(&text)
+            )?;
+        }
+
+        if options.side_by_side {
+            write!(out, "</pre></td><td{}>", style.attr("prose"))?;
+            for note in (group[0]..group[1])
+                .filter_map(|i| annotations.get(&i))
+                .flatten()
+            {
+                write!(out, "<p>{}</p>", html_escape::encode_text(note))?;
+            }
+            write!(out, "</td></tr>")?;
+        }
+
+        io::Result::Ok((out, spans))
+    };
 
-            let mut children: Vec<TagTree<'_>> = children
+    // The groups that only consist of proof commands, by their first line.
+    let mut proofs = HashSet::new();
+    if options.fold_proofs {
+        for group in starts.windows(2) {
+            let mut commands = theory
+                .commands
                 .iter()
-                .flat_map(|child| lower_node(child).into_iter())
-                .collect();
+                .filter(|cmd| (group[0]..group[1]).contains(&(cmd.line - 1)))
+                .peekable();
+            if commands.peek().is_some() && commands.all(|cmd| cmd.is_proof()) {
+                proofs.insert(group[0]);
+            }
+        }
+    }
+
+    let mut groups = vec![];
+    // The spans of each group, by its first line.
+    let mut group_spans = HashMap::new();
+    for group in starts.windows(2) {
+        let (out, spans) = render_group(&lines, group)?;
+        groups.push((group[0]..group[1], out));
+        group_spans.insert(group[0], spans);
+    }
+
+    let mut pages = vec![vec![]];
+    let mut page_size = 0;
+    for group in groups {
+        if let Some(max) = options.max_page_size {
+            if page_size > 0 && page_size + group.1.len() > max {
+                pages.push(vec![]);
+                page_size = 0;
+            }
+        }
+        page_size += group.1.len();
+        pages.last_mut().unwrap().push(group);
+    }
+
+    // The pages link to each other by their uncompressed names, which is what
+    // servers of precompressed files expect.
+    let compressed = options.out_path.extension().is_some_and(|ext| ext == "gz");
+    let out_path = if compressed {
+        options.out_path.with_extension("")
+    } else {
+        options.out_path.clone()
+    };
+    let paths: Vec<PathBuf> = (0..pages.len()).map(|i| page_path(&out_path, i)).collect();
+
+    if pages.len() > 1 {
+        // Links to definitions on other pages need to name the page. This makes the
+        // groups a bit bigger, but they stay on the pages they were assigned to.
+        let mut line_pages = vec![0; lines.len()];
+        for (i, page) in pages.iter().enumerate() {
+            for (range, _) in page {
+                line_pages[range.clone()].iter_mut().for_each(|p| *p = i);
+            }
+        }
+        for (i, page) in pages.iter_mut().enumerate() {
+            for (range, out) in page {
+                let mut changed = false;
+                for line in &mut lines[range.clone()] {
+                    changed |= map_links(line, &|href| {
+                        let id = href.strip_prefix('#')?;
+                        let page = line_pages[*definitions.get(id)?];
+                        let name = paths[page].file_name()?.to_string_lossy();
+                        (page != i).then(|| format!("{}#{}", name, id))
+                    });
+                }
+                if changed {
+                    let (new_out, spans) =
+                        render_group(&lines, &[range.start, range.end])?;
+                    *out = new_out;
+                    group_spans.insert(range.start, spans);
+                }
+            }
+        }
+    }
+
+    let stats = Stats::new(&lines, &theory);
+    // How long it took to parse and render the theory, before writing it out.
+    let render_time = started.elapsed();
+
+    let mut page_spans = vec![];
+    for (i, page) in pages.iter().enumerate() {
+        let mut writer = PageWriter::create(&paths[i], options.compress || compressed)?;
+        if options.front_matter {
+            write_front_matter(&mut writer, &nodes, &stats, &options)?;
+        }
+
+        if !options.fragment {
+            write_preamble(&mut writer, &options, style, pages.len() > 1)?;
+        }
+        if pages.len() > 1 {
+            write_page_nav(&mut writer, &pages, &paths, i, style)?;
+        }
+        if let (Some(html), 0) = (&abstract_html, i) {
+            write!(
+                writer,
+                "<section{}>{}</section>",
+                style.attr("abstract"),
+                html
+            )?;
+        }
+        if options.toc {
+            write_toc(&mut writer, &pages, &paths, i, &headings, style)?;
+        }
 
-            if let Some(s) = tooltip {
-                children = vec![TagTree::Tag {
-                    tag: Tag::Tooltip(s.to_string()),
-                    children,
-                }];
+        if options.side_by_side {
+            write!(writer, "<table{}>", style.attr("side-by-side"))?;
+        } else {
+            write!(writer, "{}", pre)?;
+        }
+        let mut spans = vec![];
+        let mut in_proof = false;
+        for (j, (range, out)) in page.iter().enumerate() {
+            let proof = proofs.contains(&range.start);
+            if proof && !in_proof {
+                // The lines of a closed details element aren't counted, so it
+                // counts them itself while closed.
+                let lines: usize = page[j..]
+                    .iter()
+                    .take_while(|(range, _)| proofs.contains(&range.start))
+                    .map(|(range, _)| range.len())
+                    .sum();
+                let counter = match style {
+                    StyleMode::Classes => {
+                        format!(r#" style="counter-increment: linenum {}""#, lines)
+                    }
+                    StyleMode::Inline => String::new(),
+                };
+                write!(
+                    writer,
+                    "<details{}{}><summary>proof</summary>",
+                    style.attr("proof"),
+                    counter
+                )?;
+            } else if !proof && in_proof {
+                write!(writer, "</details>")?;
             }
+            in_proof = proof;
 
-            if let Some(s) = class {
-                children = vec![TagTree::Tag {
-                    tag: Tag::SpanClass(s),
-                    children,
-                }];
+            let start = writer.written;
+            for (line, span) in group_spans.remove(&range.start).unwrap_or_default() {
+                let output = span.output.start + start..span.output.end + start;
+                spans.push((line, WrittenSpan { output, ..span }));
             }
+            writer.write_all(out)?;
+        }
+        if in_proof {
+            write!(writer, "</details>")?;
+        }
+        page_spans.push(spans::PageSpans {
+            path: paths[i].to_string_lossy().into_owned(),
+            spans,
+        });
+        if options.side_by_side {
+            write!(writer, "</table>")?;
+        } else {
+            write!(writer, "</pre>")?;
+        }
+        // Make it clear that the page doesn't end where the theory does.
+        if let (Some(error), true) = (&truncation, i + 1 == pages.len()) {
+            write!(
+                writer,
+                "<p{}>rendering truncated due to error: {}</p>",
+                style.attr("truncated"),
+                html_escape::encode_text(error)
+            )?;
+        }
+        if !options.fragment {
+            write!(writer, "</body></html>")?;
+        }
+        writer.finish()?;
+    }
+
+    if let Some(path) = &options.spans {
+        spans::write_json(BufWriter::new(File::create(path)?), &page_spans)?;
+    }
+
+    if let Some(path) = &options.manifest {
+        let manifest = manifest::Manifest {
+            schema_version: manifest::SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            dump: options.dump_path.to_string_lossy().into_owned(),
+            pages: page_spans.into_iter().map(|page| page.path).collect(),
+            render_ms: u64::try_from(render_time.as_millis()).unwrap_or(u64::MAX),
+            options: recorded_options(&options)?,
+        };
+        manifest::write_json(BufWriter::new(File::create(path)?), &manifest)?;
+    }
+
+    let findings = gate::Findings {
+        sorries: stats.sorries,
+        errors: lines
+            .iter()
+            .flat_map(|line| messages(line))
+            .filter(|(severity, _)| severity == "error")
+            .count(),
+        warnings: stats.warnings,
+        unknown_symbols: lines
+            .iter()
+            .flat_map(|line| {
+                let text = plain_text(line);
+                symbols::unknown_symbols(&text)
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    };
+    let fail_on = options
+        .fail_on
+        .as_ref()
+        .map_or(&[][..], |fail_on| &fail_on.0);
+    let failures = gate::failures(&findings, options.max_warnings, fail_on);
+    for failure in &failures {
+        eprintln!("failed: {}", failure);
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} quality checks failed",
+            failures.len()
+        )))
+    }
+}
 
-            children
+/// The options as they are recorded in a manifest.
+fn recorded_options(
+    options: &RenderOptions,
+) -> io::Result<serde_json::Map<String, serde_json::Value>> {
+    match serde_json::to_value(options)? {
+        serde_json::Value::Object(map) => Ok(map),
+        _ => unreachable!(),
+    }
+}
+
+/// The options recorded in the manifest at `path`, for the dump and output paths of
+/// `options`, which may not have any other options set.
+fn options_same_as(path: &Path, options: &RenderOptions) -> io::Result<RenderOptions> {
+    let paths = [
+        options.dump_path.to_string_lossy().into_owned(),
+        options.out_path.to_string_lossy().into_owned(),
+    ];
+    let parse = |args: &[String]| {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        RenderOptions::from_args(&["render"], &args).map_err(|exit| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), exit.output.trim()),
+            )
+        })
+    };
+
+    if recorded_options(options)? != recorded_options(&parse(&paths)?)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--same-as takes all the options from the manifest, so it can't be combined \
+             with other options",
+        ));
+    }
+
+    let manifest = manifest::load(path)?;
+    if manifest.version != env!("CARGO_PKG_VERSION") {
+        eprintln!(
+            "warning: {} was written by version {}, and this is version {}, so the \
+             output may differ anyway",
+            path.display(),
+            manifest.version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    let mut args = paths.to_vec();
+    args.extend(manifest::option_args(&manifest.options));
+    let mut same = parse(&args)?;
+    same.manifest = options.manifest.clone();
+    Ok(same)
+}
+
+/// The file a page is written to, which may be compressed.
+struct PageWriter {
+    file: PageFile,
+    /// How much HTML has been written, before any compression.
+    written: usize,
+}
+
+enum PageFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<File>),
+}
+
+impl PageWriter {
+    fn create(path: &Path, compress: bool) -> io::Result<PageWriter> {
+        let file = if compress {
+            let mut path = path.as_os_str().to_owned();
+            path.push(".gz");
+            PageFile::Gzip(GzEncoder::new(File::create(path)?, Compression::default()))
+        } else {
+            PageFile::Plain(BufWriter::new(File::create(path)?))
+        };
+        Ok(PageWriter { file, written: 0 })
+    }
+
+    /// Write out everything that is still buffered. Dropping the writer would do
+    /// that too, but ignore any error, and leave a compressed file incomplete.
+    fn finish(self) -> io::Result<()> {
+        match self.file {
+            PageFile::Plain(mut writer) => writer.flush(),
+            PageFile::Gzip(writer) => writer.finish().map(drop),
         }
     }
 }
 
-fn main() -> io::Result<()> {
-    let options: Options = argh::from_env();
-    let yxml = std::fs::read_to_string(&options.dump_path)?;
-    let nodes = yxml::parse(&yxml).unwrap();
-    let ir = processed_ir(&nodes);
-    let lines = split_lines(&ir);
+impl Write for PageWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match &mut self.file {
+            PageFile::Plain(writer) => writer.write(buf)?,
+            PageFile::Gzip(writer) => writer.write(buf)?,
+        };
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            PageFile::Plain(writer) => writer.flush(),
+            PageFile::Gzip(writer) => writer.flush(),
+        }
+    }
+}
 
-    let mut writer = BufWriter::new(File::create(&options.out_path)?);
+/// Links to the headings, on whichever pages they are.
+fn write_toc(
+    w: &mut impl Write,
+    pages: &[Vec<(Range<usize>, Vec<u8>)>],
+    paths: &[PathBuf],
+    current: usize,
+    headings: &HashMap<usize, Heading>,
+    style: StyleMode,
+) -> io::Result<()> {
+    let mut lines: Vec<&usize> = headings.keys().collect();
+    lines.sort();
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    write!(w, "<nav{}><ul>", style.attr("toc"))?;
+    for line in lines {
+        let heading = &headings[line];
+        let page = pages
+            .iter()
+            .position(|page| page.iter().any(|(range, _)| range.contains(line)))
+            .unwrap_or(current);
+        let file = if page == current {
+            Cow::Borrowed("")
+        } else {
+            paths[page]
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        };
+        write!(
+            w,
+            r##"<li{}><a href="{}#{}">{}</a></li>"##,
+            style.attr(&format!("toc-{}", heading.level)),
+            html_escape::encode_double_quoted_attribute(&file),
+            html_escape::encode_double_quoted_attribute(&heading.id),
+            html_escape::encode_text(&symbols::symbols_to_unicode(&heading.title))
+        )?;
+    }
+    write!(w, "</ul></nav>")
+}
 
+/// The start of an HTML document, up to the opening `<body>` tag.
+fn write_preamble(
+    writer: &mut impl Write,
+    options: &RenderOptions,
+    style: StyleMode,
+    paginated: bool,
+) -> io::Result<()> {
     write!(writer, "<!DOCTYPE html>")?;
     write!(writer, "<html>")?;
     write!(writer, "<head>")?;
     write!(writer, r#"<meta charset="utf-8">"#)?;
+    if style == StyleMode::Classes {
+        write!(
+            writer,
+            r#"<link rel="stylesheet" type="text/css" href="../assets/isabelle.css""#
+        )?;
+        if options.sri {
+            write!(
+                writer,
+                r#" integrity="{}""#,
+                assets::integrity(assets::STYLESHEET)
+            )?;
+        }
+        write!(writer, ">")?;
+    }
+    if options.lazy && paginated {
+        write!(writer, r#"<script src="../assets/lazy.js" defer"#)?;
+        if options.sri {
+            write!(
+                writer,
+                r#" integrity="{}""#,
+                assets::integrity(assets::LAZY_SCRIPT)
+            )?;
+        }
+        write!(writer, "></script>")?;
+    }
+    if options.ascii_toggle {
+        write!(writer, r#"<script src="../assets/symbols.js" defer"#)?;
+        if options.sri {
+            write!(
+                writer,
+                r#" integrity="{}""#,
+                assets::integrity(assets::SYMBOLS_SCRIPT)
+            )?;
+        }
+        write!(writer, "></script>")?;
+    }
+    if options.copy_buttons {
+        write!(writer, r#"<script src="../assets/copy.js" defer"#)?;
+        if options.sri {
+            write!(
+                writer,
+                r#" integrity="{}""#,
+                assets::integrity(assets::COPY_SCRIPT)
+            )?;
+        }
+        write!(writer, "></script>")?;
+        // The script turns the glyphs back into symbols with this. It is only data,
+        // so a content security policy doesn't apply to it.
+        let map = serde_json::to_string(&symbols::ascii_symbols())?;
+        write!(
+            writer,
+            r#"<script type="application/json" id="isabelle-symbols">{}</script>"#,
+            map.replace("</", "<\\/")
+        )?;
+    }
+    if options.max_line_length.is_some() && style == StyleMode::Classes {
+        write!(writer, r#"<script src="../assets/lines.js" defer"#)?;
+        if options.sri {
+            write!(
+                writer,
+                r#" integrity="{}""#,
+                assets::integrity(assets::LINES_SCRIPT)
+            )?;
+        }
+        write!(writer, "></script>")?;
+    }
+    write!(writer, "</head>")?;
+    write!(writer, "<body>")
+}
+
+/// The start of a page that collects information across theories, rather than
+/// showing one, up to the opening `<body>` tag.
+fn write_index_preamble(w: &mut impl Write, title: &str, class: &str) -> io::Result<()> {
+    write!(w, "<!DOCTYPE html>")?;
+    write!(w, "<html>")?;
+    write!(w, "<head>")?;
+    write!(w, r#"<meta charset="utf-8">"#)?;
     write!(
-        writer,
+        w,
         r#"<link rel="stylesheet" type="text/css" href="../assets/isabelle.css">"#
     )?;
-    write!(writer, "</head>")?;
-    write!(writer, "<body>")?;
-    write!(writer, r#"<pre class="isabelle-code">"#)?;
+    write!(w, "<title>{}</title>", html_escape::encode_text(title))?;
+    write!(w, "</head>")?;
+    write!(w, r#"<body class="{}">"#, class)
+}
 
-    for line in lines {
-        write!(writer, "<code>")?;
-        write_nodes(&mut writer, &line, false)?;
-        write!(writer, "</code>")?;
+/// The path of the `i`-th page of the output, counting from 0. The first page is
+/// written to the path given, and the others get a numeric suffix.
+fn page_path(out_path: &Path, i: usize) -> PathBuf {
+    if i == 0 {
+        return out_path.to_owned();
+    }
+
+    let stem = out_path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match out_path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, i + 1, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, i + 1),
+    };
+    out_path.with_file_name(name)
+}
+
+/// Links to the previous and next pages, and to all pages by the lines they cover.
+fn write_page_nav(
+    w: &mut impl Write,
+    pages: &[Vec<(Range<usize>, Vec<u8>)>],
+    paths: &[PathBuf],
+    current: usize,
+    style: StyleMode,
+) -> io::Result<()> {
+    let href = |i: usize| {
+        let name = paths[i].file_name().unwrap_or_default().to_string_lossy();
+        html_escape::encode_double_quoted_attribute(&name).into_owned()
+    };
+
+    write!(w, "<nav{}>", style.attr("pages"))?;
+    if current > 0 {
+        write!(
+            w,
+            r#"<a href="{}" rel="prev">previous</a> "#,
+            href(current - 1)
+        )?;
+    }
+    for (i, page) in pages.iter().enumerate() {
+        let first = page.first().map_or(0, |(lines, _)| lines.start);
+        let last = page.last().map_or(0, |(lines, _)| lines.end);
+        let label = format!("lines {}\u{2013}{}", first + 1, last);
+        if i == current {
+            write!(w, "<strong>{}</strong> ", label)?;
+        } else {
+            write!(w, r#"<a href="{}">{}</a> "#, href(i), label)?;
+        }
+    }
+    if current + 1 < pages.len() {
+        write!(w, r#"<a href="{}" rel="next">next</a>"#, href(current + 1))?;
+    }
+    write!(w, "</nav>")
+}
+
+fn check(options: CheckOptions) -> io::Result<()> {
+    let yxml = std::fs::read(&options.dump_path)?;
+    let mut buf = String::new();
+    let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+    let opts = LowerOptions {
+        strict: options.strict,
+        ..LowerOptions::default()
+    };
+    let ir = processed_ir(&nodes, opts)?;
+    write_nodes(&mut io::sink(), &ir, false, WriteOptions::default())
+}
+
+fn print_diff(options: DiffOptions) -> io::Result<()> {
+    let (old_yxml, new_yxml) = (
+        std::fs::read(&options.old_path)?,
+        std::fs::read(&options.new_path)?,
+    );
+    let (mut old_buf, mut new_buf) = (String::new(), String::new());
+    let (old, _) = parse_dump(&old_yxml, &mut old_buf, false)?;
+    let (new, _) = parse_dump(&new_yxml, &mut new_buf, false)?;
+
+    let path = |path: &[usize]| path.iter().map(|i| i.to_string()).join(".");
+    let attrs = |attrs: &yxml::Attributes<'_>| {
+        attrs
+            .iter()
+            .map(|(key, value)| format!("{}={:?}", key, value))
+            .join(" ")
+    };
+    let mut stdout = io::stdout().lock();
+    for edit in yxml::diff(&old, &new) {
+        match edit {
+            yxml::Edit::Text { path: p, old, new } => {
+                writeln!(stdout, "~ {}: {:?} -> {:?}", path(&p), old, new)?
+            }
+            yxml::Edit::Attributes { path: p, old, new } => {
+                writeln!(stdout, "~ {}: {} -> {}", path(&p), attrs(old), attrs(new))?
+            }
+            yxml::Edit::Added { path: p, node } => {
+                write!(stdout, "+ {}: {}", path(&p), node)?
+            }
+            yxml::Edit::Removed { path: p, node } => {
+                write!(stdout, "- {}: {}", path(&p), node)?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_stats(options: StatsOptions) -> io::Result<()> {
+    let yxml = std::fs::read(&options.dump_path)?;
+    let mut buf = String::new();
+    let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+    let opts = LowerOptions {
+        tooltips: false,
+        ..LowerOptions::default()
+    };
+    let lines = split_lines(&processed_ir(&nodes, opts)?);
+    let stats = Stats::new(&lines, &Theory::from_markup(&nodes));
+    stats.write_counts(&mut io::stdout().lock(), "")
+}
+
+fn export_tokens(options: TokensOptions) -> io::Result<()> {
+    let yxml = std::fs::read(&options.dump_path)?;
+    let mut buf = String::new();
+    let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+    let opts = LowerOptions {
+        tooltips: false,
+        ..LowerOptions::default()
+    };
+    let ir = processed_ir(&nodes, opts)?;
+
+    let writer: Box<dyn Write> = match &options.out_path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    match options.format {
+        tokens::Format::Json => tokens::write_json(writer, &tokens::tokens(&ir))?,
+        tokens::Format::Lsp => {
+            tokens::write_lsp(writer, &tokens::lsp_tokens(&split_lines(&ir)))?
+        }
+    }
+    Ok(())
+}
+
+fn print_tree(options: TreeOptions) -> io::Result<()> {
+    let yxml = std::fs::read(&options.dump_path)?;
+    let mut buf = String::new();
+    let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+    let mut stdout = io::stdout().lock();
+    for node in &nodes {
+        write!(stdout, "{}", node)?;
+    }
+    Ok(())
+}
+
+fn check_links(options: CheckLinksOptions) -> io::Result<()> {
+    let broken = links::check(&options.dir)?;
+    for link in &broken {
+        eprintln!("broken link: {}", link);
+    }
+
+    if broken.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} broken links", broken.len())))
+    }
+}
+
+fn write_hierarchy(options: HierarchyOptions) -> io::Result<()> {
+    let mut hierarchy = hierarchy::Hierarchy::default();
+    for dump_path in &options.dump_paths {
+        let yxml = std::fs::read(dump_path)?;
+        let mut buf = String::new();
+        let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+        let stem = dump_path.file_stem().unwrap_or_default().to_string_lossy();
+        let page = format!("{}.html", stem);
+        hierarchy.add_theory(&Theory::from_markup(&nodes), &page);
+    }
+
+    let mut writer = BufWriter::new(File::create(&options.out)?);
+    hierarchy::write_page(&mut writer, &hierarchy)?;
+    writer.flush()
+}
+
+fn write_notation(options: NotationOptions) -> io::Result<()> {
+    let mut theories = vec![];
+    for dump_path in &options.dump_paths {
+        let yxml = std::fs::read(dump_path)?;
+        let mut buf = String::new();
+        let (nodes, _) = parse_dump(&yxml, &mut buf, false)?;
+        let stem = dump_path.file_stem().unwrap_or_default().to_string_lossy();
+        let page = format!("{}.html", stem);
+        let declarations = notation::declarations(&Theory::from_markup(&nodes), &page);
+        theories.push((stem.into_owned(), declarations));
+    }
+
+    let mut writer = BufWriter::new(File::create(&options.out)?);
+    notation::write_page(&mut writer, &theories)?;
+    writer.flush()
+}
+
+fn audit_symbols(options: AuditSymbolsOptions) -> io::Result<()> {
+    let upstream_path = options.isabelle_home.join("etc").join("symbols");
+    let upstream = std::fs::read_to_string(&upstream_path)?;
+    let differences = symbols::audit(&upstream);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+
+    if let (Some(path), false) = (&options.update, differences.is_empty()) {
+        std::fs::copy(&upstream_path, path)?;
+        eprintln!("updated {}", path.display());
     }
-    write!(writer, "</pre></body></html>")?;
     Ok(())
 }
+
+fn print_man(_: ManOptions) -> io::Result<()> {
+    let name = "isabelle-markup";
+    let help = |args: &[&str]| match Options::from_args(&[name], args) {
+        Err(exit) => exit.output,
+        Ok(_) => unreachable!("--help always exits early"),
+    };
+    let commands: Vec<_> = <Command as argh::SubCommands>::COMMANDS
+        .iter()
+        .map(|command| (command.name, help(&[command.name, "--help"])))
+        .collect();
+    man::write(
+        io::stdout().lock(),
+        name,
+        "convert output of 'isabelle dump' to HTML",
+        &help(&["--help"]),
+        &commands,
+    )
+}
+
+fn main() -> io::Result<()> {
+    let options: Options = argh::from_env();
+    match options.command {
+        Command::Render(options) => render(options),
+        Command::Check(options) => check(options),
+        Command::Diff(options) => print_diff(options),
+        Command::Stats(options) => print_stats(options),
+        Command::Tokens(options) => export_tokens(options),
+        Command::Tree(options) => print_tree(options),
+        Command::CheckLinks(options) => check_links(options),
+        Command::Hierarchy(options) => write_hierarchy(options),
+        Command::Notation(options) => write_notation(options),
+        Command::AuditSymbols(options) => audit_symbols(options),
+        Command::Man(options) => print_man(options),
+    }
+}
@@ -0,0 +1,64 @@
+//! A man page, made from the help that argh prints for the tool and for each of its
+//! subcommands, as argh has no man page generator of its own.
+
+use std::io::{self, Write};
+
+/// Quote text for roff, where a backslash starts an escape and a line starting with
+/// a dot or an apostrophe is a request.
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Write the man page for the tool `name`, with its `description`, the `help` for
+/// running it, and the help of each of its subcommands, by their names. The help is
+/// kept as it is printed, lines and all.
+pub fn write(
+    mut w: impl Write,
+    name: &str,
+    description: &str,
+    help: &str,
+    commands: &[(&str, String)],
+) -> io::Result<()> {
+    writeln!(w, ".TH {} 1", name.to_uppercase())?;
+    writeln!(w, ".SH NAME")?;
+    writeln!(w, "{} \\- {}", name, escape(description))?;
+    writeln!(w, ".SH DESCRIPTION")?;
+    writeln!(w, ".nf")?;
+    writeln!(w, "{}", escape(help.trim_end()))?;
+    writeln!(w, ".fi")?;
+    writeln!(w, ".SH COMMANDS")?;
+    for (command, help) in commands {
+        writeln!(w, ".SS {}", command)?;
+        writeln!(w, ".nf")?;
+        writeln!(w, "{}", escape(help.trim_end()))?;
+        writeln!(w, ".fi")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_roff() {
+        let mut out = vec![];
+        let commands = [("tree", "Usage: tool tree\n.dots \\<alpha>\n".to_owned())];
+        write(&mut out, "tool", "Do things.", "Usage: tool\n", &commands).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ".TH TOOL 1\n.SH NAME\ntool \\- Do things.\n.SH DESCRIPTION\n.nf\nUsage: tool\n\
+             .fi\n.SH COMMANDS\n.SS tree\n.nf\nUsage: tool tree\n\\&.dots \\e<alpha>\n.fi\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! A record of a build: the pages written, and every option they were rendered with,
+//! defaults included. Passing it to `--same-as` renders with the same options again,
+//! to find out why two renders of a theory differ.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Version of the JSON format written by [`write_json`], described by
+/// `schemas/manifest.schema.json`. Bump this whenever a change could break consumers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: u32,
+    /// The version of this tool that did the build.
+    pub version: String,
+    pub dump: String,
+    pub pages: Vec<String>,
+    /// How long it took to parse and render the theory, in milliseconds. It is
+    /// kept out of the pages, so that the same render always gives the same bytes.
+    #[serde(default)]
+    pub render_ms: u64,
+    /// The options, by the name of their flag without the leading dashes.
+    pub options: Map<String, Value>,
+}
+
+pub fn write_json(w: impl io::Write, manifest: &Manifest) -> serde_json::Result<()> {
+    serde_json::to_writer_pretty(w, manifest)
+}
+
+pub fn load(path: &Path) -> io::Result<Manifest> {
+    let json = fs::read_to_string(path)?;
+    let manifest: Manifest = serde_json::from_str(&json).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    })?;
+    if manifest.schema_version != SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: unsupported manifest version {}",
+                path.display(),
+                manifest.schema_version
+            ),
+        ));
+    }
+    Ok(manifest)
+}
+
+/// The command line arguments that give the options, as recorded in a manifest.
+/// Switches that are off and options that aren't set are left out.
+pub fn option_args(options: &Map<String, Value>) -> Vec<String> {
+    let mut args = vec![];
+    for (name, value) in options {
+        let flag = format!("--{}", name);
+        match value {
+            Value::Bool(true) => args.push(flag),
+            Value::Bool(false) | Value::Null => (),
+            Value::Array(values) => {
+                for value in values {
+                    args.extend([flag.clone(), arg_value(value)]);
+                }
+            }
+            value => args.extend([flag, arg_value(value)]),
+        }
+    }
+    args
+}
+
+fn arg_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn options_to_args() {
+        let options = serde_json::json!({
+            "class-style": "pygments",
+            "fast": true,
+            "toc": false,
+            "annotations": null,
+            "max-page-size": 100000,
+            "redact": ["foo", "bar"],
+        });
+        assert_eq!(
+            option_args(options.as_object().unwrap()),
+            [
+                "--class-style",
+                "pygments",
+                "--fast",
+                "--max-page-size",
+                "100000",
+                "--redact",
+                "foo",
+                "--redact",
+                "bar",
+            ]
+        );
+    }
+}
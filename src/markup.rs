@@ -0,0 +1,179 @@
+//! Typed access to the properties that Isabelle attaches to markup elements.
+//!
+//! YXML only knows about string attributes, but many of the attributes Isabelle
+//! emits have a fixed meaning across elements, like the source position of an
+//! entity's definition. The types here decode them once, so that tools don't have
+//! to parse the strings by hand.
+
+use std::str::FromStr;
+use yxml::{Attributes, Node};
+
+/// A position in the sources, as given by the `line`, `offset`, `end_offset`, `file`
+/// and `id` properties.
+///
+/// Offsets count Isabelle symbols starting at 1, rather than bytes, so `\<alpha>`
+/// takes up one position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Position<'a> {
+    pub line: Option<usize>,
+    pub offset: Option<usize>,
+    pub end_offset: Option<usize>,
+    pub file: Option<&'a str>,
+    /// The ID of the command or document version the position refers to.
+    pub id: Option<i64>,
+}
+
+/// A reference to a formal entity, such as a constant or a fact, from an `entity`
+/// element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entity<'a> {
+    /// What kind of entity this is, like `constant` or `fact`.
+    pub kind: &'a str,
+    pub name: &'a str,
+    /// Set on the occurrence that defines the entity, as a serial number unique
+    /// within the session.
+    pub def: Option<i64>,
+    /// Set on occurrences that refer to the entity, matching the `def` of its
+    /// definition.
+    pub reference: Option<i64>,
+    /// Where the entity is defined, if that is known.
+    pub position: Option<Position<'a>>,
+    /// The file the entity is defined in, set on references to entities from other
+    /// theories.
+    pub def_file: Option<&'a str>,
+}
+
+/// A markup element that applies to a known range of the sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report<'n, 'a> {
+    pub name: &'a str,
+    pub position: Position<'a>,
+    pub node: &'n Node<'a>,
+}
+
+fn parse_attr<T: FromStr>(attrs: &Attributes<'_>, key: &str) -> Option<T> {
+    attrs.get(key)?.parse().ok()
+}
+
+impl<'a> Position<'a> {
+    /// Decode the position properties among `attrs`, or return `None` if there are
+    /// none. Properties that fail to parse are treated as missing.
+    pub fn from_attrs(attrs: &Attributes<'a>) -> Option<Position<'a>> {
+        let position = Position {
+            line: parse_attr(attrs, "line"),
+            offset: parse_attr(attrs, "offset"),
+            end_offset: parse_attr(attrs, "end_offset"),
+            file: attrs.get("file"),
+            id: parse_attr(attrs, "id"),
+        };
+        if position == Position::default() {
+            None
+        } else {
+            Some(position)
+        }
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// Decode an `entity` element. Returns `None` for other nodes.
+    pub fn from_node(node: &Node<'a>) -> Option<Entity<'a>> {
+        match node {
+            Node::Tag {
+                name: "entity",
+                attrs,
+                ..
+            } => Some(Entity {
+                kind: attrs.get("kind").unwrap_or(""),
+                name: attrs.get("name").unwrap_or(""),
+                def: parse_attr(attrs, "def"),
+                reference: parse_attr(attrs, "ref"),
+                position: Position::from_attrs(attrs),
+                def_file: attrs.get("def_file"),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// All the elements in `nodes` and below that carry a source position, in document
+/// order.
+pub fn reports<'n, 'a>(nodes: &'n [Node<'a>]) -> Vec<Report<'n, 'a>> {
+    nodes
+        .iter()
+        .flat_map(|node| node.walk())
+        .filter_map(|(_, node)| match node {
+            Node::Tag { name, attrs, .. } => Some(Report {
+                name,
+                position: Position::from_attrs(attrs)?,
+                node,
+            }),
+            Node::Text(_) => None,
+        })
+        .collect()
+}
+
+/// All the entities in the source covered by `nodes`, in document order. Those in
+/// the bodies of `xml_elem` tags, which only show up in tooltips, are left out.
+pub fn entities<'a>(nodes: &[Node<'a>]) -> Vec<Entity<'a>> {
+    fn collect<'a>(nodes: &[Node<'a>], out: &mut Vec<Entity<'a>>) {
+        for node in nodes {
+            if let Node::Tag { name, children, .. } = node {
+                if *name == "xml_body" {
+                    continue;
+                }
+                out.extend(Entity::from_node(node));
+                collect(children, out);
+            }
+        }
+    }
+
+    let mut entities = vec![];
+    collect(nodes, &mut entities);
+    entities
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entities_and_reports() {
+        let input = "\x05\x06entity\x06ref=42\x06offset=3\x06end_offset=6\
+            \x06id=-7\x06name=foo\x06kind=constant\x05foo\x05\x06\x05\
+            \x05\x06keyword1\x05by\x05\x06\x05\
+            \x05\x06entity\x06kind=fact\x06name=bar\x06def=x\x05bar\x05\x06\x05\
+            \x05\x06entity\x06ref=5\x06def_file=~~/src/HOL/Nat.thy\x05Suc\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+
+        let position = Position {
+            offset: Some(3),
+            end_offset: Some(6),
+            id: Some(-7),
+            ..Position::default()
+        };
+        assert_eq!(
+            Entity::from_node(&nodes[0]),
+            Some(Entity {
+                kind: "constant",
+                name: "foo",
+                def: None,
+                reference: Some(42),
+                position: Some(position),
+                def_file: None,
+            })
+        );
+        assert_eq!(Entity::from_node(&nodes[1]), None);
+        assert_eq!(Entity::from_node(&nodes[2]).unwrap().def, None);
+        let suc = Entity::from_node(&nodes[3]).unwrap();
+        assert_eq!(suc.def_file, Some("~~/src/HOL/Nat.thy"));
+
+        let names: Vec<&str> =
+            entities(&nodes).iter().map(|entity| entity.name).collect();
+        assert_eq!(names, ["foo", "bar", ""]);
+
+        let reports = reports(&nodes);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].name, "entity");
+        assert_eq!(reports[0].position, position);
+    }
+}
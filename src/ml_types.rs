@@ -0,0 +1,113 @@
+//! Laying out ML types over several lines, for the tooltips of ML code. Isabelle
+//! prints them on one line, which gets hard to read for the types of functions that
+//! take a context, a few records and a continuation.
+
+use itertools::Itertools;
+
+/// The width that types are broken to fit in, when possible.
+pub const WIDTH: usize = 60;
+
+/// Split `ty` at the occurrences of `sep` outside of any brackets.
+fn split_top_level<'t>(ty: &'t str, sep: &str) -> Vec<&'t str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in ty.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && i >= start && ty[i..].starts_with(sep) => {
+                parts.push(ty[start..i].trim());
+                start = i + sep.len();
+            }
+            _ => (),
+        }
+    }
+    parts.push(ty[start..].trim());
+    parts
+}
+
+/// The position of the bracket closing the one that `ty` starts with.
+fn closing_bracket(ty: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in ty.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn layout_at(ty: &str, indent: usize, width: usize) -> String {
+    let ty = ty.trim();
+    if indent + ty.chars().count() <= width {
+        return ty.to_owned();
+    }
+
+    // Functions and tuples get a line for each part, ending with the separator.
+    for sep in ["->", "*"] {
+        let parts = split_top_level(ty, sep);
+        if parts.len() > 1 {
+            let separator = format!(" {}\n{}", sep, " ".repeat(indent));
+            return parts
+                .iter()
+                .map(|part| layout_at(part, indent, width))
+                .join(&separator);
+        }
+    }
+
+    // Type arguments like in `(string, int) Table.table`, parenthesized types and
+    // records get a line for each of their parts, aligned after the bracket.
+    let (open, close) = match (ty.chars().next(), closing_bracket(ty)) {
+        (Some(open @ ('(' | '{')), Some(close)) => (open, close),
+        _ => return ty.to_owned(),
+    };
+    let indent = indent + open.len_utf8();
+    let separator = format!(",\n{}", " ".repeat(indent));
+    let inner = split_top_level(&ty[open.len_utf8()..close], ",")
+        .iter()
+        .map(|part| layout_at(part, indent, width))
+        .join(&separator);
+    format!("{}{}{}", open, inner, &ty[close..])
+}
+
+/// Break a type over several lines, so that it fits in `width` columns if possible.
+/// Types that already fit are returned as they are.
+pub fn layout(ty: &str, width: usize) -> String {
+    layout_at(ty, 0, width)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_types_stay() {
+        assert_eq!(layout("int -> int", 20), "int -> int");
+    }
+
+    #[test]
+    fn breaks_long_types() {
+        let ty = "Proof.context -> (string * typ) list -> {name: binding, pos: Position.T} -> thm";
+        assert_eq!(
+            layout(ty, 30),
+            "Proof.context ->\n\
+             (string * typ) list ->\n\
+             {name: binding,\n \
+             pos: Position.T} ->\n\
+             thm"
+        );
+        assert_eq!(
+            layout("(string, int Symtab.table * bool) Symtab.table", 30),
+            "(string,\n \
+             int Symtab.table * bool) Symtab.table"
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! A structured view of a theory, as the sequence of commands it consists of.
+//!
+//! Isabelle wraps each outer syntax command in a `<command_span>` tag, which carries
+//! the name and kind of the command. Everything in between (whitespace and comments
+//! at the outer level) does not belong to any command, but is still accounted for in
+//! the source positions.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Range;
+use yxml::{Attributes, Node, Visit};
+
+/// A theory, as a sequence of commands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theory<'a> {
+    pub commands: Vec<Command<'a>>,
+}
+
+/// A single outer syntax command, like `lemma` or `by`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command<'a> {
+    /// The name of the command keyword.
+    pub name: &'a str,
+    /// The kind of the command, as declared in the outer syntax, e.g. `thy_goal`.
+    pub kind: Option<&'a str>,
+    /// Byte range of the command within the theory source.
+    pub span: Range<usize>,
+    /// The line the command starts on, counting from 1.
+    pub line: usize,
+    /// The markup within the command span.
+    pub markup: Vec<Node<'a>>,
+}
+
+/// A fragment of source text, along with the markup that applies to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    /// Byte range of the token within the theory source.
+    pub span: Range<usize>,
+    /// Names of the markup elements enclosing the token, outermost first.
+    pub markup: Vec<&'a str>,
+}
+
+/// The information in the `theory` command that starts a theory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    pub imports: Vec<String>,
+}
+
+/// A heading of the document, from a command like `section`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heading {
+    /// From 1 for `chapter` to 4 for `subsubsection` and anything below.
+    pub level: usize,
+    /// The text of the heading, with symbols as in the source.
+    pub title: String,
+    /// An ID for linking to the heading, derived from the title.
+    pub id: String,
+    /// The line the heading starts on, counting from 1.
+    pub line: usize,
+}
+
+/// The commands that start a heading, outermost first.
+const HEADING_COMMANDS: &[&str] = &[
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+/// Commands that state a theorem, which may be named.
+const THEOREM_COMMANDS: &[&str] = &[
+    "lemma",
+    "theorem",
+    "corollary",
+    "proposition",
+    "schematic_goal",
+];
+
+impl<'a> Theory<'a> {
+    pub fn from_markup(nodes: &[Node<'a>]) -> Theory<'a> {
+        let mut commands = vec![];
+        let mut offset = 0;
+        let mut line = 1;
+        for node in nodes {
+            let len = source_len(node);
+            if let Node::Tag {
+                name: "command_span",
+                attrs,
+                children,
+            } = node
+            {
+                commands.push(Command {
+                    name: attrs.get("name").unwrap_or(""),
+                    kind: attrs.get("kind"),
+                    span: offset..offset + len,
+                    line,
+                    markup: children.clone(),
+                });
+            }
+
+            offset += len;
+            line += source_newlines(node);
+        }
+
+        Theory { commands }
+    }
+
+    /// The theory name and imports, if the theory starts with a `theory` command.
+    pub fn header(&self) -> Option<Header> {
+        let command = self.commands.iter().find(|cmd| cmd.name == "theory")?;
+        let text = command.text();
+        let mut words = text.split_whitespace().skip(1);
+        let name = words.next()?.trim_matches('"').to_owned();
+        let imports = match words.next() {
+            Some("imports") => words
+                .take_while(|&word| !matches!(word, "begin" | "keywords" | "abbrevs"))
+                .map(|word| word.trim_matches('"').to_owned())
+                .collect(),
+            _ => vec![],
+        };
+
+        Some(Header { name, imports })
+    }
+
+    /// An anchor for each command, for linking to it from elsewhere. Named theorems
+    /// use their name, and other commands get an ID derived from a hash of their
+    /// text, so that editing one command doesn't change the anchors of the others.
+    /// Repeated IDs get a numeric suffix to keep them unique.
+    pub fn anchors(&self) -> Vec<String> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        self.commands
+            .iter()
+            .map(|cmd| {
+                let base = cmd.theorem_name().unwrap_or_else(|| {
+                    let digest = Sha256::digest(cmd.text().as_bytes());
+                    let hex: String =
+                        digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+                    format!("{}-{}", cmd.name, hex)
+                });
+                let count = seen.entry(base.clone()).or_insert(0);
+                *count += 1;
+                if *count == 1 {
+                    base
+                } else {
+                    format!("{}-{}", base, count)
+                }
+            })
+            .collect()
+    }
+
+    /// The headings of the theory, in order. Like [`Theory::anchors`], repeated IDs
+    /// get a numeric suffix.
+    pub fn headings(&self) -> Vec<Heading> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        self.commands
+            .iter()
+            .filter_map(|cmd| {
+                let level = HEADING_COMMANDS.iter().position(|&name| name == cmd.name)?;
+                let text = cmd.text();
+                let title = text.trim_start().strip_prefix(cmd.name)?.trim();
+                let title = ["\\<open>", "‹", "\""]
+                    .iter()
+                    .zip(["\\<close>", "›", "\""])
+                    .find_map(|(open, close)| {
+                        title.strip_prefix(open)?.strip_suffix(close)
+                    })
+                    .unwrap_or(title)
+                    .trim();
+
+                let slug = title
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_lowercase)
+                    .collect::<Vec<_>>()
+                    .join("-");
+                let base = format!("{}-{}", cmd.name, slug);
+                let count = seen.entry(base.clone()).or_insert(0);
+                *count += 1;
+                let id = if *count == 1 {
+                    base
+                } else {
+                    format!("{}-{}", base, count)
+                };
+
+                Some(Heading {
+                    level: (level + 1).min(4),
+                    title: title.to_owned(),
+                    id,
+                    line: cmd.line,
+                })
+            })
+            .collect()
+    }
+
+    /// The names given to theorems in the theory, in order.
+    pub fn theorem_names(&self) -> Vec<String> {
+        self.commands
+            .iter()
+            .filter_map(Command::theorem_name)
+            .collect()
+    }
+}
+
+impl<'a> Command<'a> {
+    /// The name given to the theorem stated by this command, if any.
+    pub fn theorem_name(&self) -> Option<String> {
+        if !THEOREM_COMMANDS.contains(&self.name) {
+            return None;
+        }
+
+        let text = self.text();
+        let rest = text.trim_start().strip_prefix(self.name)?.trim_start();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == ':' || c == '[')
+            .unwrap_or(rest.len());
+        let (name, after) = rest.split_at(end);
+        if !name.is_empty() && after.trim_start().starts_with([':', '[']) {
+            Some(name.to_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Whether the command states a theorem, like `lemma`.
+    pub fn states_theorem(&self) -> bool {
+        THEOREM_COMMANDS.contains(&self.name)
+    }
+
+    /// Whether the command is part of a proof, like `apply`, `proof` or `qed`.
+    pub fn is_proof(&self) -> bool {
+        is_proof_kind(self.kind)
+    }
+
+    /// The source text of the command.
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|token| token.text).collect()
+    }
+
+    /// Split the command into tokens of text with uniform markup.
+    pub fn tokens(&self) -> Vec<Token<'a>> {
+        let mut collector = TokenCollector {
+            offset: self.span.start,
+            stack: vec![],
+            tokens: vec![],
+        };
+        collector.visit_nodes(&self.markup);
+        collector.tokens
+    }
+}
+
+/// Whether a command of this kind belongs to a proof, including the commands that
+/// conclude one.
+fn is_proof_kind(kind: Option<&str>) -> bool {
+    kind.is_some_and(|kind| {
+        kind.starts_with("prf_") || kind.starts_with("qed") || kind == "next_block"
+    })
+}
+
+/// Replace the proofs of the named theorems with a `(* TODO *)` placeholder, so that
+/// exercises can be rendered from the same dump as the solutions. The whitespace
+/// around a proof is kept.
+pub fn redact_proofs<'a>(nodes: &[Node<'a>], theorems: &[String]) -> Vec<Node<'a>> {
+    let placeholder = || Node::tag("comment").child("(* TODO *)").build();
+    let theory = Theory::from_markup(nodes);
+    let mut commands = theory.commands.iter();
+    let mut out = vec![];
+    // Whether we're after a redacted theorem, and whether its proof has started.
+    let mut redacting = false;
+    let mut in_proof = false;
+    // Nodes between commands, which are only kept if the proof doesn't continue.
+    let mut pending = vec![];
+    for node in nodes {
+        match node {
+            Node::Tag {
+                name: "command_span",
+                ..
+            } => {
+                let command = commands.next().unwrap();
+                if redacting && is_proof_kind(command.kind) {
+                    if in_proof {
+                        pending.clear();
+                    } else {
+                        out.append(&mut pending);
+                        in_proof = true;
+                    }
+                    continue;
+                }
+
+                if in_proof {
+                    out.push(placeholder());
+                }
+                out.append(&mut pending);
+                out.push(node.clone());
+                redacting = command
+                    .theorem_name()
+                    .is_some_and(|name| theorems.contains(&name));
+                in_proof = false;
+            }
+            _ if redacting => pending.push(node.clone()),
+            _ => out.push(node.clone()),
+        }
+    }
+
+    if in_proof {
+        out.push(placeholder());
+    }
+    out.append(&mut pending);
+    out
+}
+
+/// Length of the source text covered by `node`. The bodies of `xml_elem` tags are
+/// generated by Isabelle and don't correspond to any source text.
+fn source_len(node: &Node<'_>) -> usize {
+    match node {
+        Node::Text(s) => s.len(),
+        Node::Tag {
+            name: "xml_body", ..
+        } => 0,
+        Node::Tag { children, .. } => children.iter().map(source_len).sum(),
+    }
+}
+
+/// Number of line breaks in the source text covered by `node`.
+fn source_newlines(node: &Node<'_>) -> usize {
+    match node {
+        Node::Text(s) => s.matches('\n').count(),
+        Node::Tag {
+            name: "xml_body", ..
+        } => 0,
+        Node::Tag { children, .. } => children.iter().map(source_newlines).sum(),
+    }
+}
+
+struct TokenCollector<'a> {
+    offset: usize,
+    stack: Vec<&'a str>,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Visit<'a> for TokenCollector<'a> {
+    fn visit_tag(&mut self, name: &'a str, _: &Attributes<'a>, children: &[Node<'a>]) {
+        if name != "xml_body" {
+            self.stack.push(name);
+            self.visit_nodes(children);
+            self.stack.pop();
+        }
+    }
+
+    fn visit_text(&mut self, text: &'a str) {
+        self.tokens.push(Token {
+            text,
+            span: self.offset..self.offset + text.len(),
+            markup: self.stack.clone(),
+        });
+        self.offset += text.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INPUT: &str = "\x05\x06command_span\x06name=lemma\x06kind=thy_goal\x05\
+        \x05\x06keyword1\x05lemma\x05\x06\x05 \x05\x06xml_elem\x05\
+        \x05\x06xml_body\x05'a\x05\x06\x05x\x05\x06\x05\x05\x06\x05\n\
+        \x05\x06command_span\x06name=by\x05by simp\x05\x06\x05";
+
+    #[test]
+    fn commands() {
+        let nodes = yxml::parse(INPUT).unwrap();
+        let theory = Theory::from_markup(&nodes);
+        assert_eq!(theory.commands.len(), 2);
+        assert_eq!(theory.commands[0].name, "lemma");
+        assert_eq!(theory.commands[0].kind, Some("thy_goal"));
+        assert_eq!(theory.commands[0].span, 0..7);
+        assert_eq!(theory.commands[1].name, "by");
+        assert_eq!(theory.commands[1].kind, None);
+        assert_eq!(theory.commands[1].span, 8..15);
+        assert_eq!(theory.commands[1].line, 2);
+    }
+
+    #[test]
+    fn header_and_theorems() {
+        let input = "\x05\x06command_span\x06name=theory\x05\
+            theory Foo imports Main \"HOL-Library.Bar\" begin\x05\x06\x05\n\
+            \x05\x06command_span\x06name=lemma\x05lemma foo [simp]: x\x05\x06\x05\n\
+            \x05\x06command_span\x06name=lemma\x05lemma \"x = x\"\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+        let theory = Theory::from_markup(&nodes);
+        assert_eq!(
+            theory.header(),
+            Some(Header {
+                name: "Foo".to_owned(),
+                imports: vec!["Main".to_owned(), "HOL-Library.Bar".to_owned()],
+            })
+        );
+        assert_eq!(theory.theorem_names(), ["foo"]);
+    }
+
+    #[test]
+    fn headings() {
+        let input = "\x05\x06command_span\x06name=section\x05section \\<open>Lists \
+            and Sets\\<close>\x05\x06\x05\n\
+            \x05\x06command_span\x06name=paragraph\x05paragraph \"x\"\x05\x06\x05\n\
+            \x05\x06command_span\x06name=paragraph\x05paragraph \"x\"\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+        let headings = Theory::from_markup(&nodes).headings();
+        assert_eq!(
+            headings[0],
+            Heading {
+                level: 2,
+                title: "Lists and Sets".to_owned(),
+                id: "section-lists-and-sets".to_owned(),
+                line: 1,
+            }
+        );
+        assert_eq!(headings[1].level, 4);
+        assert_eq!(headings[1].id, "paragraph-x");
+        assert_eq!(headings[2].id, "paragraph-x-2");
+    }
+
+    #[test]
+    fn anchors() {
+        let input = "\x05\x06command_span\x06name=lemma\x05lemma foo: x\x05\x06\x05\n\
+            \x05\x06command_span\x06name=by\x05by simp\x05\x06\x05\n\
+            \x05\x06command_span\x06name=by\x05by simp\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+        let anchors = Theory::from_markup(&nodes).anchors();
+        assert_eq!(anchors[0], "foo");
+        assert!(anchors[1].starts_with("by-"));
+        assert_eq!(anchors[2], format!("{}-2", anchors[1]));
+    }
+
+    #[test]
+    fn redact() {
+        let input = "\x05\x06command_span\x06name=lemma\x05lemma foo: x\x05\x06\x05\n  \
+            \x05\x06command_span\x06name=apply\x06kind=prf_script\x05apply simp\x05\x06\x05\n  \
+            \x05\x06command_span\x06name=done\x06kind=qed_script\x05done\x05\x06\x05\n\
+            \x05\x06command_span\x06name=end\x06kind=thy_end\x05end\x05\x06\x05";
+        let nodes = yxml::parse(input).unwrap();
+        let redacted = redact_proofs(&nodes, &["foo".to_owned()]);
+        let text: String = Theory::from_markup(&redacted)
+            .commands
+            .iter()
+            .map(|cmd| cmd.text())
+            .collect::<Vec<_>>()
+            .join("|");
+        assert_eq!(text, "lemma foo: x|end");
+        let theory = Theory::from_markup(&nodes);
+        let proof: Vec<bool> = theory.commands.iter().map(Command::is_proof).collect();
+        assert_eq!(proof, [false, true, true, false]);
+        assert!(theory.commands[0].states_theorem());
+        assert!(!theory.commands[1].states_theorem());
+        assert_eq!(
+            yxml::to_string(&redacted[1..4]).unwrap(),
+            "\n  \x05\x06comment\x05(* TODO *)\x05\x06\x05\n"
+        );
+        assert_eq!(redact_proofs(&nodes, &[]), nodes);
+    }
+
+    #[test]
+    fn tokens() {
+        let nodes = yxml::parse(INPUT).unwrap();
+        let theory = Theory::from_markup(&nodes);
+        assert_eq!(
+            theory.commands[0].tokens(),
+            [
+                Token {
+                    text: "lemma",
+                    span: 0..5,
+                    markup: vec!["keyword1"],
+                },
+                Token {
+                    text: " ",
+                    span: 5..6,
+                    markup: vec![],
+                },
+                Token {
+                    text: "x",
+                    span: 6..7,
+                    markup: vec!["xml_elem"],
+                },
+            ]
+        );
+    }
+}
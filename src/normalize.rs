@@ -0,0 +1,54 @@
+//! Normalizing the text of a dump before it is rendered, for the sake of search
+//! engines and other tools that compare text codepoint by codepoint.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Codepoints that look the same as a more common one, and are replaced with it by
+/// `--replace-confusables`.
+static CONFUSABLES: &[(char, &str)] = &[
+    ('\u{a0}', " "),       // no-break space
+    ('\u{b5}', "\u{3bc}"), // micro sign, as Greek small letter mu
+    ('\u{2010}', "-"),     // hyphen
+    ('\u{2011}', "-"),     // non-breaking hyphen
+    ('\u{2212}', "-"),     // minus sign
+    ('\u{2018}', "'"),     // left single quotation mark
+    ('\u{2019}', "'"),     // right single quotation mark
+    ('\u{201c}', "\""),    // left double quotation mark
+    ('\u{201d}', "\""),    // right double quotation mark
+    ('\u{200b}', ""),      // zero width space
+    ('\u{feff}', ""),      // zero width no-break space
+];
+
+/// Bring `input` to Unicode normalization form C and/or replace confusable
+/// codepoints. Isabelle symbols are written as `\<name>` in a dump, so this only
+/// affects literal non-ASCII text.
+pub fn normalize(input: &str, nfc: bool, replace_confusables: bool) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut push = |c: char| match CONFUSABLES.iter().find(|(from, _)| *from == c) {
+        Some((_, to)) if replace_confusables => output.push_str(to),
+        _ => output.push(c),
+    };
+
+    if nfc {
+        input.nfc().for_each(&mut push);
+    } else {
+        input.chars().for_each(&mut push);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nfc_and_confusables() {
+        let input = "e\u{301}\u{2011}\u{b5}\\<alpha>";
+        assert_eq!(normalize(input, false, false), input);
+        assert_eq!(
+            normalize(input, true, false),
+            "\u{e9}\u{2011}\u{b5}\\<alpha>"
+        );
+        assert_eq!(normalize(input, true, true), "\u{e9}-\u{3bc}\\<alpha>");
+    }
+}
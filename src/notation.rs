@@ -0,0 +1,342 @@
+//! A reference page for the notation a development introduces, mapping the syntax
+//! to what it stands for.
+//!
+//! Entries come from `notation`, `type_notation`, `syntax` and `abbreviation`
+//! commands. The mixfix annotations are found in the text of each command, and paired
+//! up in order with the names they are declared for.
+
+use isabelle_markup::markup::{self, Entity};
+use isabelle_markup::model::Theory;
+use isabelle_markup::symbols::symbols_to_unicode;
+use std::io::{self, Write};
+
+/// A piece of notation declared by a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    /// The mixfix annotation, like `(infixl "⊕" 65)`, or the name of an
+    /// abbreviation that doesn't have one.
+    pub syntax: String,
+    /// What the syntax stands for: a constant, a syntax constant, or the equation of
+    /// an abbreviation.
+    pub meaning: String,
+    /// Where the entity behind the syntax is defined, if that is known.
+    pub href: Option<String>,
+    /// The name of the command the notation is declared with.
+    pub command: String,
+    /// Where the declaration is, in a theory rendered with `--anchors`.
+    pub declared_at: String,
+}
+
+/// The parts of a command's text that matter for finding the notation in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece<'t> {
+    /// A string literal or cartouche outside of any parentheses, without its
+    /// delimiters.
+    Literal(&'t str),
+    /// Parentheses with a literal inside, like a mixfix annotation.
+    Mixfix(&'t str),
+    /// Anything else, up to the next whitespace.
+    Word(&'t str),
+}
+
+/// The end of the string literal or cartouche starting at `start`, or of the text if
+/// it isn't closed.
+fn literal_end(text: &str, start: usize) -> usize {
+    let quoted = text[start..].starts_with('"');
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices() {
+        match c {
+            '\\' if quoted => escaped = !escaped,
+            '"' if quoted && i > 0 && !escaped => return start + i + 1,
+            '‹' if !quoted => depth += 1,
+            '›' if !quoted => {
+                depth -= 1;
+                if depth == 0 {
+                    return start + i + c.len_utf8();
+                }
+            }
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
+
+/// The end of the comment starting at `start`, which may contain other comments, or
+/// of the text if it isn't closed.
+fn comment_end(text: &str, start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < text.len() {
+        if text[i..].starts_with("(*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*)") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// The end of the parentheses starting at `start`, or of the text if they aren't
+/// closed.
+fn group_end(text: &str, start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while let Some(c) = text[i..].chars().next() {
+        match c {
+            '"' | '‹' => {
+                i = literal_end(text, i);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => (),
+        }
+        i += c.len_utf8();
+    }
+    text.len()
+}
+
+/// Split the text of a command into pieces, leaving out comments and parentheses
+/// without any literals, like the mode in `notation (output)`.
+fn pieces(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        if rest.starts_with("(*") {
+            i = comment_end(text, i);
+        } else if c == '"' || c == '‹' {
+            let end = literal_end(text, i);
+            let literal = &text[i + c.len_utf8()..end];
+            let literal = literal
+                .strip_suffix(if c == '"' { '"' } else { '›' })
+                .unwrap_or(literal);
+            pieces.push(Piece::Literal(literal));
+            i = end;
+        } else if c == '(' {
+            let end = group_end(text, i);
+            let group = &text[i..end];
+            if group.contains(['"', '‹']) {
+                pieces.push(Piece::Mixfix(group));
+            }
+            i = end;
+        } else if c.is_whitespace() {
+            i += c.len_utf8();
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '(' | '"' | '‹'))
+                .unwrap_or(rest.len());
+            pieces.push(Piece::Word(&rest[..len]));
+            i += len;
+        }
+    }
+    pieces
+}
+
+/// The notation declared in a theory that is rendered to `page`, in order.
+pub fn declarations(theory: &Theory<'_>, page: &str) -> Vec<Declaration> {
+    let mut declarations = vec![];
+    for (command, anchor) in theory.commands.iter().zip(theory.anchors()) {
+        let text = symbols_to_unicode(&command.text());
+        let pieces = pieces(&text);
+        let entities: Vec<Entity<'_>> = markup::entities(&command.markup)
+            .into_iter()
+            .filter(|entity| entity.kind != "command")
+            .collect();
+        let mixfixes = pieces.iter().filter_map(|piece| match piece {
+            Piece::Mixfix(mixfix) => Some(*mixfix),
+            _ => None,
+        });
+        let declaration =
+            |syntax: &str, meaning: &str, entity: Option<&Entity<'_>>| Declaration {
+                syntax: syntax.to_owned(),
+                meaning: meaning.to_owned(),
+                href: entity.and_then(|entity| crate::entity_href_from(entity, page)),
+                command: command.name.to_owned(),
+                declared_at: format!("{}#{}", page, anchor),
+            };
+
+        match command.name {
+            "notation" | "type_notation" => {
+                let names: Vec<&str> = pieces
+                    .iter()
+                    .skip(1)
+                    .filter_map(|piece| match piece {
+                        Piece::Word(word) if *word != "and" => Some(*word),
+                        _ => None,
+                    })
+                    .collect();
+                // Without an entity for each name, there's no telling which is which.
+                let entities = (entities.len() == names.len()).then_some(&entities);
+                for (i, (name, mixfix)) in names.iter().zip(mixfixes).enumerate() {
+                    let entity = entities.map(|entities| &entities[i]);
+                    declarations.push(declaration(mixfix, name, entity));
+                }
+            }
+            "syntax" => {
+                let names = pieces.windows(2).filter_map(|pair| match pair {
+                    [Piece::Literal(name), Piece::Word("::")] => Some(*name),
+                    _ => None,
+                });
+                for (name, mixfix) in names.zip(mixfixes) {
+                    declarations.push(declaration(mixfix, name, None));
+                }
+            }
+            "abbreviation" => {
+                let name = match pieces.get(1) {
+                    Some(Piece::Word(word)) if !matches!(*word, "::" | "where") => {
+                        Some(*word)
+                    }
+                    _ => None,
+                };
+                let equation = pieces.iter().rev().find_map(|piece| match piece {
+                    Piece::Literal(literal) => Some(*literal),
+                    _ => None,
+                });
+                let equation = match equation {
+                    Some(equation) => equation,
+                    None => continue,
+                };
+                let lhs = equation.split('≡').next().unwrap_or_default();
+                let syntax = match (mixfixes.clone().next(), name) {
+                    (Some(mixfix), _) => mixfix,
+                    (None, Some(name)) => name,
+                    (None, None) => lhs.split_whitespace().next().unwrap_or(lhs),
+                };
+                let entity = entities.iter().find(|entity| entity.def.is_some());
+                declarations.push(declaration(syntax, equation.trim(), entity));
+            }
+            _ => (),
+        }
+    }
+    declarations
+}
+
+/// Write a page with a table of the notation declared in each theory, given along
+/// with the theory's name.
+pub fn write_page(
+    w: &mut impl Write,
+    theories: &[(String, Vec<Declaration>)],
+) -> io::Result<()> {
+    crate::write_index_preamble(w, "Notation", "notation")?;
+    for (name, declarations) in theories {
+        if declarations.is_empty() {
+            continue;
+        }
+
+        write!(w, "<h2>{}</h2>", html_escape::encode_text(name))?;
+        write!(
+            w,
+            "<table><tr><th>Syntax</th><th>Stands for</th><th>Declared by</th></tr>"
+        )?;
+        for declaration in declarations {
+            let meaning = html_escape::encode_text(&declaration.meaning);
+            write!(
+                w,
+                "<tr><td><code>{}</code></td>",
+                html_escape::encode_text(&declaration.syntax)
+            )?;
+            match &declaration.href {
+                Some(href) => write!(
+                    w,
+                    r#"<td><a href="{}"><code>{}</code></a></td>"#,
+                    html_escape::encode_double_quoted_attribute(href),
+                    meaning
+                )?,
+                None => write!(w, "<td><code>{}</code></td>", meaning)?,
+            }
+            write!(
+                w,
+                r#"<td><a href="{}">{}</a></td></tr>"#,
+                html_escape::encode_double_quoted_attribute(&declaration.declared_at),
+                html_escape::encode_text(&declaration.command)
+            )?;
+        }
+        write!(w, "</table>")?;
+    }
+    write!(w, "</body>")?;
+    write!(w, "</html>")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_into_pieces() {
+        assert_eq!(
+            pieces(
+                r#"notation (output) plus (infixl "⊕" 65) (* see (* nested *) *) and times ("_ ⊗ (_)")"#
+            ),
+            [
+                Piece::Word("notation"),
+                Piece::Word("plus"),
+                Piece::Mixfix(r#"(infixl "⊕" 65)"#),
+                Piece::Word("and"),
+                Piece::Word("times"),
+                Piece::Mixfix(r#"("_ ⊗ (_)")"#),
+            ]
+        );
+        assert_eq!(
+            pieces(r#"abbreviation ‹a ≡ ‹b››"#),
+            [Piece::Word("abbreviation"), Piece::Literal("a ≡ ‹b›")]
+        );
+    }
+
+    #[test]
+    fn collects_declarations() {
+        let command = |name: &str, body: &str| {
+            format!(
+                "\x05\x06command_span\x06name={}\x05{}\x05\x06\x05\n",
+                name, body
+            )
+        };
+        let yxml = [
+            command(
+                "notation",
+                "notation \x05\x06entity\x06kind=constant\x06name=Groups.plus\x06ref=1\
+                 \x06def_file=~~/src/HOL/Groups.thy\x05plus\x05\x06\x05 (infixl \"⊕\" 65)",
+            ),
+            command("syntax", "syntax \"_sum\" :: \"'a ⇒ 'a\" (\"∑_\" [10] 10)"),
+            command(
+                "abbreviation",
+                "abbreviation \x05\x06entity\x06kind=constant\x06name=Foo.double\x06def=2\
+                 \x05double\x05\x06\x05 where \"double x ≡ x + x\"",
+            ),
+            command("lemma", "lemma \"double x = 2 * x\""),
+        ]
+        .concat();
+        let nodes = yxml::parse(&yxml).unwrap();
+        let declarations = declarations(&Theory::from_markup(&nodes), "Foo.html");
+
+        let summary: Vec<_> = declarations
+            .iter()
+            .map(|d| (&d.syntax[..], &d.meaning[..], d.href.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (r#"(infixl "⊕" 65)"#, "plus", Some("Groups.html#entity-1")),
+                (r#"("∑_" [10] 10)"#, "_sum", None),
+                ("double", "double x ≡ x + x", Some("Foo.html#entity-2")),
+            ]
+        );
+        assert!(declarations[0]
+            .declared_at
+            .starts_with("Foo.html#notation-"));
+        assert_eq!(declarations[1].command, "syntax");
+    }
+}
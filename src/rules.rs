@@ -0,0 +1,164 @@
+//! The table that decides what each markup element is shown as: the classes it is
+//! highlighted with, its tooltip, or whether it is left out entirely.
+//!
+//! A default table is bundled, and users can put their own rules in front of it with
+//! `--markup-rules`, to support markup from newer versions of Isabelle without
+//! waiting for a release. The format is described by
+//! `schemas/markup-rules.schema.json`.
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use yxml::Attributes;
+
+/// What to do with the markup elements of a given name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// The name of the markup element.
+    pub name: String,
+    /// Attributes the element must have for the rule to apply, with their values.
+    #[serde(default)]
+    pub attrs: BTreeMap<String, String>,
+    /// Leave out the element along with everything in it.
+    #[serde(default)]
+    pub ignore: bool,
+    /// The classes to highlight the contents with.
+    pub class: Option<String>,
+    /// An attribute whose value is added to the classes, if the element has it.
+    pub class_attr: Option<String>,
+    /// The text of the tooltip.
+    pub tooltip: Option<String>,
+    /// Show the `xml_body` child as the tooltip, after this prefix.
+    pub body_tooltip: Option<String>,
+    /// How the `xml_body` child of the tooltip is laid out.
+    #[serde(default)]
+    pub body_format: BodyFormat,
+    /// With `body_tooltip`, start the tooltip with the type variable the element
+    /// covers, if it covers one, like in `'a :: linorder`.
+    #[serde(default)]
+    pub name_type_variable: bool,
+    /// Show the `xml_body` child as a message of this severity, like `warning`.
+    pub message: Option<String>,
+    /// An attribute naming the embedded language the contents are written in, which
+    /// is written as their `data-language`.
+    pub language_attr: Option<String>,
+}
+
+/// How to lay out the body of a tooltip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyFormat {
+    /// With the markup in it, as given.
+    #[default]
+    Markup,
+    /// As an ML type, broken over several lines if it's long. Only applies to
+    /// bodies without any markup.
+    MlType,
+}
+
+/// The rules, in the order they are tried in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    pub rules: Vec<Rule>,
+}
+
+static DEFAULT_RULES: Lazy<Rules> = Lazy::new(|| {
+    serde_json::from_str(include_str!("markup-rules.json"))
+        .expect("invalid bundled rules")
+});
+
+impl Rule {
+    fn matches(&self, name: &str, attrs: &Attributes<'_>) -> bool {
+        self.name == name
+            && self
+                .attrs
+                .iter()
+                .all(|(key, value)| attrs.get(key) == Some(value.as_str()))
+    }
+
+    /// The classes for an element this rule applies to.
+    pub fn classes(&self, attrs: &Attributes<'_>) -> Option<String> {
+        let mut classes = self.class.clone()?;
+        if let Some(value) = self.class_attr.as_deref().and_then(|key| attrs.get(key)) {
+            classes.push(' ');
+            classes.push_str(value);
+        }
+        Some(classes)
+    }
+}
+
+impl Rules {
+    /// The bundled rules.
+    pub fn bundled() -> &'static Rules {
+        &DEFAULT_RULES
+    }
+
+    /// Load the rules at `path`, to be tried before the bundled ones.
+    pub fn load(path: &Path) -> io::Result<Rules> {
+        let json = fs::read_to_string(path)?;
+        let mut rules: Rules = serde_json::from_str(&json).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), err),
+            )
+        })?;
+        rules.rules.extend(DEFAULT_RULES.rules.iter().cloned());
+        Ok(rules)
+    }
+
+    /// The first rule that applies to an element.
+    pub fn find(&self, name: &str, attrs: &Attributes<'_>) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(name, attrs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn user_rules_come_first() {
+        let path =
+            std::env::temp_dir().join(format!("rules-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"rules": [{"name": "free", "class": "variable"},
+                          {"name": "xml_elem", "attrs": {"xml_name": "fancy"}, "ignore": true}]}"#,
+        )
+        .unwrap();
+        let rules = Rules::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let nodes = yxml::parse(
+            "\x05\x06keyword1\x06kind=command\x05\x05\x06\x05\
+             \x05\x06xml_elem\x06xml_name=fancy\x05\x05\x06\x05\
+             \x05\x06xml_elem\x06xml_name=typing\x05\x05\x06\x05",
+        )
+        .unwrap();
+        let attrs: Vec<_> = nodes
+            .iter()
+            .map(|node| match node {
+                yxml::Node::Tag { attrs, .. } => attrs,
+                yxml::Node::Text(_) => unreachable!(),
+            })
+            .collect();
+
+        let free = rules.find("free", attrs[0]).unwrap();
+        assert_eq!(free.class.as_deref(), Some("variable"));
+        assert_eq!(free.tooltip, None);
+        let keyword = rules.find("keyword1", attrs[0]).unwrap();
+        assert_eq!(
+            keyword.classes(attrs[0]).as_deref(),
+            Some("keyword1 command")
+        );
+        assert!(rules.find("xml_elem", attrs[1]).unwrap().ignore);
+        let typing = rules.find("xml_elem", attrs[2]).unwrap();
+        assert_eq!(typing.body_tooltip.as_deref(), Some(":: "));
+        assert_eq!(Rules::bundled().find("xml_elem", attrs[1]), None);
+    }
+}
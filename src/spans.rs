@@ -0,0 +1,66 @@
+//! Export of the elements written for the markup, with where they ended up in the
+//! output, so that tools can check the HTML without parsing it again.
+
+use isabelle_markup::ir::WrittenSpan;
+use serde::Serialize;
+use std::io;
+
+/// Version of the JSON format written by [`write_json`], described by
+/// `schemas/spans.schema.json`. Bump this whenever a change could break consumers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Export<'a> {
+    schema_version: u32,
+    pages: Vec<Page<'a>>,
+}
+
+#[derive(Serialize)]
+struct Page<'a> {
+    path: &'a str,
+    spans: Vec<Span<'a>>,
+}
+
+#[derive(Serialize)]
+struct Span<'a> {
+    line: usize,
+    output_start: usize,
+    output_end: usize,
+    classes: &'a str,
+    source_start: usize,
+    source_end: usize,
+}
+
+/// The spans of one page, with the line of the source each is on, counting from 1.
+/// Output positions are byte offsets into the page, and source positions are byte
+/// offsets into the line.
+pub struct PageSpans {
+    pub path: String,
+    pub spans: Vec<(usize, WrittenSpan)>,
+}
+
+pub fn write_json(w: impl io::Write, pages: &[PageSpans]) -> serde_json::Result<()> {
+    let pages = pages
+        .iter()
+        .map(|page| Page {
+            path: &page.path,
+            spans: page
+                .spans
+                .iter()
+                .map(|(line, span)| Span {
+                    line: *line,
+                    output_start: span.output.start,
+                    output_end: span.output.end,
+                    classes: &span.classes,
+                    source_start: span.source.start,
+                    source_end: span.source.end,
+                })
+                .collect(),
+        })
+        .collect();
+    let export = Export {
+        schema_version: SCHEMA_VERSION,
+        pages,
+    };
+    serde_json::to_writer(w, &export)
+}
@@ -1,62 +1,137 @@
+use crate::ir::WriteOptions;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::io::{self, prelude::*};
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Debug)]
 pub struct Symbol {
-    unicode: Option<char>,
+    /// What the symbol is displayed as. Usually a single code point, but a symbol
+    /// with several `code:` entries is displayed as their sequence, such as a base
+    /// character followed by a variation selector.
+    unicode: Option<String>,
     name: &'static str,
     abbrev: Vec<&'static str>,
 }
 
 impl Symbol {
-    fn tooltip(&self) -> String {
-        let mut tooltip = format!("\\<{}>", self.name);
+    /// The ways to type the symbol, and the code points it is displayed as, as
+    /// pairs of a label and a value.
+    fn input_methods(&self) -> Vec<(&'static str, String)> {
+        let mut rows = vec![("symbol", format!("\\<{}>", self.name))];
         for abbrev in &self.abbrev {
-            tooltip.push_str("\nabbreviation: ");
-            tooltip.push_str(abbrev);
+            rows.push(("abbreviation", abbrev.to_string()));
+        }
+        if let Some(unicode) = &self.unicode {
+            let code_points = unicode.chars().map(|c| format!("U+{:04X}", c as u32));
+            rows.push(("code point", code_points.format(" ").to_string()));
         }
-        html_escape::encode_text(&tooltip).into_owned()
+        rows
     }
 
-    fn write(&self, mut w: impl Write, with_tooltips: bool) -> io::Result<()> {
-        if with_tooltips {
-            let tooltip = format!(r#"<span class="tooltip">{}</span>"#, self.tooltip());
-            if let Some(c) = self.unicode {
-                write!(w, r#"<span class="has-tooltip">{}{}</span>"#, c, tooltip)
-            } else {
+    /// The tooltip as plain text, with one line per input method.
+    fn tooltip_text(&self) -> String {
+        self.input_methods()
+            .iter()
+            .map(|(label, value)| format!("{}: {}", label, value))
+            .join("\n")
+    }
+
+    /// The tooltip as HTML, laid out as a table by the stylesheet.
+    fn tooltip(&self) -> String {
+        let mut tooltip = String::from(r#"<span class="symbol-info">"#);
+        for (label, value) in self.input_methods() {
+            tooltip.push_str(&format!(
+                "<span>{}</span><span>{}</span>",
+                label,
+                html_escape::encode_text(&value)
+            ));
+        }
+        tooltip.push_str("</span>");
+        tooltip
+    }
+
+    /// Write what the symbol is shown as, along with the symbol itself if the reader
+    /// can switch to it.
+    fn write_glyph(
+        &self,
+        mut w: impl Write,
+        glyph: &str,
+        opts: WriteOptions,
+    ) -> io::Result<()> {
+        if opts.ascii_toggle {
+            write!(w, r#"<span class="symbol-unicode">{}</span>"#, glyph)?;
+            write_ascii(w, &format!("\\<{}>", self.name))
+        } else {
+            write!(w, "{}", glyph)
+        }
+    }
+
+    fn write(
+        &self,
+        mut w: impl Write,
+        with_tooltips: bool,
+        opts: WriteOptions,
+    ) -> io::Result<()> {
+        let style = opts.style;
+        // Control symbols without a glyph are shown by their name, like `theory`.
+        let (glyph, control) = match &self.unicode {
+            Some(c) => (c.as_str(), ""),
+            None => {
                 assert!(self.name.starts_with('^'));
-                write!(
-                    w,
-                    r#"<span class="control has-tooltip">{}{}</span>"#,
-                    &self.name[1..],
-                    tooltip
-                )
+                (&self.name[1..], "control")
             }
-        } else {
-            if let Some(c) = self.unicode {
-                write!(w, "{}", c)
+        };
+        if with_tooltips && opts.title_tooltips {
+            let title = self.tooltip_text();
+            let title = html_escape::encode_double_quoted_attribute(&title);
+            let attr = if control.is_empty() {
+                String::new()
             } else {
-                assert!(self.name.starts_with('^'));
-                write!(w, r#"<span class="control">{}</span>"#, &self.name[1..])
+                style.attr(control)
+            };
+            write!(w, r#"<span{} title="{}">"#, attr, title)?;
+            self.write_glyph(&mut w, glyph, opts)?;
+            write!(w, "</span>")
+        } else if with_tooltips {
+            let tooltip = format!(
+                r#"<span class="tooltip symbol-tooltip">{}</span>"#,
+                self.tooltip()
+            );
+            if control.is_empty() {
+                write!(w, r#"<span class="has-tooltip">"#)?;
+            } else {
+                write!(w, "<span{}>", style.attr("control has-tooltip"))?;
             }
+            self.write_glyph(&mut w, glyph, opts)?;
+            write!(w, "{}</span>", tooltip)
+        } else if control.is_empty() {
+            self.write_glyph(w, glyph, opts)
+        } else {
+            write!(w, "<span{}>", style.attr(control))?;
+            self.write_glyph(&mut w, glyph, opts)?;
+            write!(w, "</span>")
         }
     }
 }
 
 static SYMBOL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\\<([a-zA-Z_^]+)>").unwrap());
 
-static SYMBOLS: Lazy<HashMap<&'static str, Symbol>> = Lazy::new(parse_symbols);
+static SYMBOLS: Lazy<HashMap<&'static str, Symbol>> =
+    Lazy::new(|| parse_symbols(SYMBOL_DATA));
 
-fn parse_symbols() -> HashMap<&'static str, Symbol> {
-    static SYMBOL_DATA: &str = include_str!("symbols");
+/// The bundled copy of Isabelle's `etc/symbols`.
+static SYMBOL_DATA: &str = include_str!("symbols");
 
+fn parse_symbols(data: &'static str) -> HashMap<&'static str, Symbol> {
     let mut symbols = HashMap::new();
 
-    for line in SYMBOL_DATA.split('\n') {
+    for line in data.split('\n') {
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
@@ -82,7 +157,8 @@ fn parse_symbols() -> HashMap<&'static str, Symbol> {
                     assert!(val.starts_with("0x"));
                     let val = &val[2..];
                     let num = u32::from_str_radix(val, 16).unwrap();
-                    symbol.unicode = Some(num.try_into().unwrap());
+                    let c: char = num.try_into().unwrap();
+                    symbol.unicode.get_or_insert_with(String::new).push(c);
                 }
                 "abbrev:" => symbol.abbrev.push(val),
                 "group:" | "argument:" | "font:" => (),
@@ -98,18 +174,452 @@ fn parse_symbols() -> HashMap<&'static str, Symbol> {
     symbols
 }
 
-pub fn render_symbols(s: &str, mut w: impl Write, with_tooltips: bool) -> io::Result<()> {
-    let mut last_symbol = 0;
-    for captures in SYMBOL_RE.captures_iter(s) {
+/// The element that a control symbol like `\<^sub>` wraps the following symbol in.
+fn control_element(name: &str) -> Option<&'static str> {
+    match name {
+        "^sub" => Some("sub"),
+        "^sup" => Some("sup"),
+        "^bold" => Some("b"),
+        _ => None,
+    }
+}
+
+/// The length of the symbol a control symbol applies to, at the start of `s`. This
+/// is either a `\<name>` symbol, or an extended grapheme cluster, so that combining
+/// marks and emoji sequences aren't split. Returns `None` if there's no symbol the
+/// control symbol could apply to, like at the end of the text.
+fn next_symbol_len(s: &str) -> Option<usize> {
+    if let Some(captures) = SYMBOL_RE.captures(s) {
         let range = captures.get(0).unwrap().range();
-        let symbol = &SYMBOLS[&captures[1]];
+        if range.start == 0 {
+            return (!captures[1].starts_with('^')).then_some(range.end);
+        }
+    }
+
+    s.graphemes(true)
+        .next()
+        .filter(|grapheme| !grapheme.trim().is_empty())
+        .map(str::len)
+}
+
+/// Characters that coding fonts tend to combine into ligatures, like `=>` or `::`.
+const LIGATURE_CHARS: &str = "!#$%&*+-./:<=>?@^|~";
+
+/// How characters that wouldn't show up, like control characters, are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unprintables {
+    /// As a visible replacement, like `␛` for an escape, with the code point shown
+    /// on hover.
+    Glyph,
+    /// As an escape sequence, like `\u{1b}`.
+    Escape,
+    /// As they are.
+    Raw,
+}
+
+impl FromStr for Unprintables {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "glyph" => Ok(Unprintables::Glyph),
+            "escape" => Ok(Unprintables::Escape),
+            "raw" => Ok(Unprintables::Raw),
+            _ => Err(format!("unknown way to show unprintables: {}", s)),
+        }
+    }
+}
+
+/// Whether the character wouldn't show up, or could change how the text around it
+/// is displayed, like the bidirectional overrides. Line breaks and tabs are fine,
+/// as are the joiners used in emoji sequences.
+fn is_unprintable(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\n' | '\t' | '\r'))
+        || matches!(
+            c,
+            '\u{200b}'
+                | '\u{200e}'
+                | '\u{200f}'
+                | '\u{202a}'..='\u{202e}'
+                | '\u{2060}'..='\u{2069}'
+                | '\u{feff}'
+        )
+}
+
+/// Write an unprintable character as `opts.unprintables` says.
+fn write_unprintable(
+    mut w: impl Write,
+    c: char,
+    with_tooltips: bool,
+    opts: WriteOptions,
+) -> io::Result<()> {
+    let style = opts.style;
+    if opts.unprintables == Unprintables::Escape {
+        return write!(
+            w,
+            "<span{}>\\u{{{:x}}}</span>",
+            style.attr("unprintable"),
+            c as u32
+        );
+    }
+
+    // The Control Pictures block has a glyph for each of the C0 controls.
+    let glyph = match c {
+        '\0'..='\u{1f}' => char::from_u32(0x2400 + c as u32).unwrap(),
+        '\u{7f}' => '\u{2421}',
+        _ => '\u{fffd}',
+    };
+    let code_point = format!("U+{:04X}", c as u32);
+    if with_tooltips && opts.title_tooltips {
+        write!(
+            w,
+            r#"<span{} title="code point: {}">{}</span>"#,
+            style.attr("unprintable"),
+            code_point,
+            glyph
+        )
+    } else if with_tooltips {
         write!(
             w,
-            "{}",
-            html_escape::encode_text(&s[last_symbol..range.start]),
-        )?;
-        symbol.write(&mut w, with_tooltips)?;
-        last_symbol = range.end;
+            r#"<span{}>{}<span class="tooltip symbol-tooltip"><span class="symbol-info"><span>code point</span><span>{}</span></span></span></span>"#,
+            style.attr("unprintable has-tooltip"),
+            glyph,
+            code_point
+        )
+    } else {
+        write!(w, "<span{}>{}</span>", style.attr("unprintable"), glyph)
+    }
+}
+
+/// Write how a symbol is typed, for when the reader switches to that.
+pub(crate) fn write_ascii(mut w: impl Write, symbol: &str) -> io::Result<()> {
+    write!(
+        w,
+        r#"<span class="symbol-ascii">{}</span>"#,
+        html_escape::encode_text(symbol)
+    )
+}
+
+/// Write text without any symbols in it, escaped for HTML, with the unprintable
+/// characters replaced as `opts.unprintables` says.
+fn write_text(
+    mut w: impl Write,
+    s: &str,
+    with_tooltips: bool,
+    opts: WriteOptions,
+) -> io::Result<()> {
+    let mut rest = s;
+    if opts.unprintables != Unprintables::Raw {
+        while let Some(i) = rest.find(is_unprintable) {
+            write_printable(&mut w, &rest[..i], opts)?;
+            let c = rest[i..].chars().next().unwrap();
+            write_unprintable(&mut w, c, with_tooltips, opts)?;
+            rest = &rest[i + c.len_utf8()..];
+        }
+    }
+    write_printable(w, rest, opts)
+}
+
+/// Write text without any symbols in it, escaped for HTML. With
+/// `opts.break_ligatures`, a zero-width non-joiner is put between adjacent
+/// characters that could form a ligature, so that e.g. `=>` isn't shown as `⇒`.
+fn write_printable(mut w: impl Write, s: &str, opts: WriteOptions) -> io::Result<()> {
+    if !opts.break_ligatures {
+        return write!(w, "{}", html_escape::encode_text(s));
+    }
+
+    let mut text = String::with_capacity(s.len());
+    let mut prev = None;
+    for c in s.chars() {
+        if prev.is_some_and(|prev| LIGATURE_CHARS.contains(prev))
+            && LIGATURE_CHARS.contains(c)
+        {
+            text.push('\u{200c}');
+        }
+        text.push(c);
+        prev = Some(c);
+    }
+    write!(w, "{}", html_escape::encode_text(&text))
+}
+
+pub fn render_symbols(
+    s: &str,
+    mut w: impl Write,
+    with_tooltips: bool,
+    opts: WriteOptions,
+) -> io::Result<()> {
+    let mut last_symbol = 0;
+    while let Some(captures) = SYMBOL_RE.captures(&s[last_symbol..]) {
+        let range = captures.get(0).unwrap().range();
+        let (start, end) = (last_symbol + range.start, last_symbol + range.end);
+        write_text(&mut w, &s[last_symbol..start], with_tooltips, opts)?;
+        last_symbol = end;
+
+        let element = control_element(&captures[1]);
+        match element.zip(next_symbol_len(&s[end..])) {
+            Some((element, len)) => {
+                let next = &s[end..end + len];
+                if opts.ascii_toggle {
+                    write_ascii(&mut w, &captures[0])?;
+                }
+                write!(w, "<{}>", element)?;
+                match SYMBOL_RE
+                    .captures(next)
+                    .and_then(|next| SYMBOLS.get(&next[1]))
+                {
+                    Some(symbol) => symbol.write(&mut w, with_tooltips, opts)?,
+                    None => write_text(&mut w, next, with_tooltips, opts)?,
+                }
+                write!(w, "</{}>", element)?;
+                last_symbol += len;
+            }
+            None => match SYMBOLS.get(&captures[1]) {
+                Some(symbol) => symbol.write(&mut w, with_tooltips, opts)?,
+                // Symbols that aren't in the table are shown the way they are typed.
+                None => write_text(&mut w, &captures[0], with_tooltips, opts)?,
+            },
+        }
+    }
+    write_text(&mut w, &s[last_symbol..], with_tooltips, opts)
+}
+
+/// The entries of a symbols file, as the symbol and its properties with whitespace
+/// normalized.
+fn symbol_entries(data: &str) -> BTreeMap<&str, String> {
+    data.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?, parts.join(" ")))
+        })
+        .collect()
+}
+
+/// Compare the bundled symbols with `upstream`, the contents of `etc/symbols` in an
+/// Isabelle distribution, and describe each difference.
+pub fn audit(upstream: &str) -> Vec<String> {
+    let bundled = symbol_entries(SYMBOL_DATA);
+    let upstream = symbol_entries(upstream);
+    let mut differences = vec![];
+    for (symbol, properties) in &bundled {
+        match upstream.get(symbol) {
+            None => differences.push(format!("missing upstream: {}", symbol)),
+            Some(new) if new != properties => differences.push(format!(
+                "changed: {} from {:?} to {:?}",
+                symbol, properties, new
+            )),
+            Some(_) => (),
+        }
+    }
+    for symbol in upstream.keys() {
+        if !bundled.contains_key(symbol) {
+            differences.push(format!("new upstream: {}", symbol));
+        }
+    }
+    differences
+}
+
+/// The symbols by the glyph they are shown as, for turning text back into the way it
+/// is typed. Where several symbols share a glyph, the first one in the symbols file
+/// is used.
+pub fn ascii_symbols() -> BTreeMap<&'static str, String> {
+    let symbols: &'static HashMap<&'static str, Symbol> = &SYMBOLS;
+    let mut ascii = BTreeMap::new();
+    let lines = SYMBOL_DATA.lines().filter(|line| !line.starts_with('#'));
+    for captures in lines.filter_map(|line| SYMBOL_RE.captures(line)) {
+        let symbol = &symbols[&captures[1]];
+        if let Some(glyph) = symbol.unicode.as_deref() {
+            ascii.entry(glyph).or_insert_with(|| captures[0].to_owned());
+        }
+    }
+    ascii
+}
+
+/// The number of Isabelle symbols in `s`, counting each `\<name>` symbol as one, like
+/// the offsets in markup do.
+pub fn symbol_count(s: &str) -> usize {
+    // The names of symbols are ASCII, so they take up a byte for each character.
+    let names: usize = SYMBOL_RE.find_iter(s).map(|m| m.len() - 1).sum();
+    s.chars().count() - names
+}
+
+/// The symbols in `s` that aren't in the symbol table, like `\<foo>`, in order.
+pub fn unknown_symbols(s: &str) -> impl Iterator<Item = &str> {
+    SYMBOL_RE
+        .captures_iter(s)
+        .filter(|captures| !SYMBOLS.contains_key(&captures[1]))
+        .map(|captures| captures.get(0).unwrap().as_str())
+}
+
+/// Replace the symbols in `s` with their Unicode equivalents, where there is one.
+pub fn symbols_to_unicode(s: &str) -> String {
+    SYMBOL_RE
+        .replace_all(s, |captures: &regex::Captures<'_>| {
+            match SYMBOLS
+                .get(&captures[1])
+                .and_then(|symbol| symbol.unicode.as_ref())
+            {
+                Some(c) => c.clone(),
+                None => captures[0].to_owned(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(s: &str) -> String {
+        render_with(s, false)
+    }
+
+    fn render_with(s: &str, break_ligatures: bool) -> String {
+        let opts = WriteOptions {
+            break_ligatures,
+            ..WriteOptions::default()
+        };
+        let mut buf = Vec::new();
+        render_symbols(s, &mut buf, false, opts).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn tooltips() {
+        let symbol = &SYMBOLS["Rightarrow"];
+        assert_eq!(
+            symbol.tooltip_text(),
+            "symbol: \\<Rightarrow>\nabbreviation: .>\nabbreviation: =>\n\
+             code point: U+21D2"
+        );
+        assert_eq!(
+            symbol.tooltip(),
+            "<span class=\"symbol-info\"><span>symbol</span><span>\\&lt;Rightarrow&gt;</span>\
+             <span>abbreviation</span><span>.&gt;</span>\
+             <span>abbreviation</span><span>=&gt;</span>\
+             <span>code point</span><span>U+21D2</span></span>"
+        );
+    }
+
+    #[test]
+    fn unknown_symbols_stay() {
+        assert_eq!(
+            render("\\<foo> \\<^sub>\\<baz>"),
+            "\\&lt;foo&gt; <sub>\\&lt;baz&gt;</sub>"
+        );
+        assert_eq!(
+            unknown_symbols("\\<alpha>\\<foo>\\<^bar>").collect::<Vec<_>>(),
+            ["\\<foo>", "\\<^bar>"]
+        );
+    }
+
+    #[test]
+    fn break_ligatures() {
+        assert_eq!(render_with("a => b", false), "a =&gt; b");
+        assert_eq!(
+            render_with("x <= y\\<Rightarrow>::", true),
+            "x &lt;\u{200c}= y⇒:\u{200c}:"
+        );
+    }
+
+    #[test]
+    fn audit_differences() {
+        let mut upstream = SYMBOL_DATA.replace(
+            "\\<zero>                 code: 0x01d7ec  group: digit",
+            "\\<zero> code: 0x01d7ec group: digit font: Foo",
+        );
+        upstream = upstream.replace("\\<one>", "\\<uno>");
+        let mut differences = audit(&upstream);
+        differences.sort();
+        assert_eq!(
+            differences,
+            [
+                "changed: \\<zero> from \"code: 0x01d7ec group: digit\" to \
+                 \"code: 0x01d7ec group: digit font: Foo\"",
+                "missing upstream: \\<one>",
+                "new upstream: \\<uno>",
+            ]
+        );
+        assert!(audit(SYMBOL_DATA).is_empty());
+    }
+
+    #[test]
+    fn code_point_sequences() {
+        let symbols = parse_symbols(
+            "\\<heart>  code: 0x002665  code: 0x00fe0e  group: misc\n\
+             \\<spade>  code: 0x002660",
+        );
+        assert_eq!(
+            symbols["heart"].unicode.as_deref(),
+            Some("\u{2665}\u{fe0e}")
+        );
+        assert_eq!(symbols["spade"].unicode.as_deref(), Some("\u{2660}"));
+    }
+
+    #[test]
+    fn unprintables() {
+        let render_as = |s: &str, unprintables| {
+            let opts = WriteOptions {
+                title_tooltips: true,
+                unprintables,
+                ..WriteOptions::default()
+            };
+            let mut buf = Vec::new();
+            render_symbols(s, &mut buf, true, opts).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            render_as("a\u{1b}b\u{202e}\n", Unprintables::Glyph),
+            "a<span class=\"unprintable\" title=\"code point: U+001B\">\u{241b}</span>b\
+             <span class=\"unprintable\" title=\"code point: U+202E\">\u{fffd}</span>\n"
+        );
+        assert_eq!(
+            render_as("a\u{7}", Unprintables::Escape),
+            "a<span class=\"unprintable\">\\u{7}</span>"
+        );
+        assert_eq!(render_as("a\u{7}\t", Unprintables::Raw), "a\u{7}\t");
+        assert_eq!(render("👨\u{200d}👩"), "👨\u{200d}👩");
+    }
+
+    #[test]
+    fn control_symbols() {
+        assert_eq!(
+            render("x\\<^sub>1\\<^sup>\\<alpha>"),
+            "x<sub>1</sub><sup>α</sup>"
+        );
+        assert_eq!(
+            render("\\<^bold>e\u{301}\u{301}x"),
+            "<b>e\u{301}\u{301}</b>x"
+        );
+        assert_eq!(render("\\<^bold>👍🏽!"), "<b>👍🏽</b>!");
+        assert_eq!(render("x\\<^sub>"), "x⇩");
+        assert_eq!(render("x\\<^sub> "), "x⇩ ");
+    }
+
+    #[test]
+    fn inverse_map() {
+        let ascii = ascii_symbols();
+        assert_eq!(ascii["α"], "\\<alpha>");
+        assert_eq!(ascii["⟶"], "\\<longrightarrow>");
+        assert_eq!(ascii["⇩"], "\\<^sub>");
+        assert!(!ascii.contains_key("theory"));
+    }
+
+    #[test]
+    fn ascii_toggle() {
+        let opts = WriteOptions {
+            ascii_toggle: true,
+            ..WriteOptions::default()
+        };
+        let mut buf = Vec::new();
+        render_symbols("\\<alpha>\\<^sub>1 \\<^theory>", &mut buf, false, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "<span class=\"symbol-unicode\">α</span><span class=\"symbol-ascii\">\\&lt;alpha&gt;</span>\
+             <span class=\"symbol-ascii\">\\&lt;^sub&gt;</span><sub>1</sub> \
+             <span class=\"control\"><span class=\"symbol-unicode\">theory</span>\
+             <span class=\"symbol-ascii\">\\&lt;^theory&gt;</span></span>"
+        );
     }
-    write!(w, "{}", html_escape::encode_text(&s[last_symbol..]))
 }
@@ -0,0 +1,362 @@
+//! Export of the highlighting as a flat stream of tokens.
+//!
+//! This lets editors and web highlighters reuse the classes assigned from Isabelle's
+//! markup instead of approximating them with regex-based grammars. Like the semantic
+//! token conventions of most editors, offsets and lengths are counted in UTF-16 code
+//! units, and only text that has any classes assigned is included.
+//!
+//! The tokens can also be written as the semantic tokens of the Language Server
+//! Protocol, for editor plugins to pass on as they are.
+
+use isabelle_markup::ir::{Tag, TagTree, Visit};
+use serde::Serialize;
+use std::io;
+use std::str::FromStr;
+
+/// Version of the JSON format written by [`write_json`], described by
+/// `schemas/tokens.schema.json`. Bump this whenever a change could break consumers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Export<'a> {
+    schema_version: u32,
+    tokens: &'a [Token],
+}
+
+pub fn write_json(w: impl io::Write, tokens: &[Token]) -> serde_json::Result<()> {
+    let export = Export {
+        schema_version: SCHEMA_VERSION,
+        tokens,
+    };
+    serde_json::to_writer(w, &export)
+}
+
+/// The formats the tokens can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The format described by `schemas/tokens.schema.json`, with the classes of
+    /// each token.
+    Json,
+    /// Semantic tokens as in the Language Server Protocol, along with their legend.
+    Lsp,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "lsp" => Ok(Format::Lsp),
+            _ => Err(format!("unknown token format: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Token {
+    pub offset: usize,
+    pub length: usize,
+    pub classes: Vec<String>,
+}
+
+pub fn tokens(input: &[TagTree<'_>]) -> Vec<Token> {
+    let mut collector = TokenCollector {
+        classes: vec![],
+        offset: 0,
+        tokens: vec![],
+    };
+    collector.visit_nodes(input);
+    collector.tokens
+}
+
+struct TokenCollector {
+    classes: Vec<String>,
+    offset: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Visit<'a> for TokenCollector {
+    fn visit_tag(&mut self, tag: &Tag, children: &[TagTree<'a>]) {
+        let depth = self.classes.len();
+        if let Tag::SpanClass(cls) = tag {
+            self.classes
+                .extend(cls.split_whitespace().map(str::to_owned));
+        }
+        self.visit_nodes(children);
+        self.classes.truncate(depth);
+    }
+
+    fn visit_text(&mut self, text: &'a str) {
+        let length = text.encode_utf16().count();
+        if !self.classes.is_empty() {
+            match self.tokens.last_mut() {
+                Some(last)
+                    if last.offset + last.length == self.offset
+                        && last.classes == self.classes =>
+                {
+                    last.length += length;
+                }
+                _ => self.tokens.push(Token {
+                    offset: self.offset,
+                    length,
+                    classes: self.classes.clone(),
+                }),
+            }
+        }
+        self.offset += length;
+    }
+}
+
+/// The token types of the LSP legend, in the order their indices refer to.
+pub const LSP_TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "typeParameter",
+    "function",
+    "variable",
+    "parameter",
+    "operator",
+    "number",
+    "string",
+    "macro",
+    "comment",
+];
+
+/// The token modifiers of the LSP legend, in the order of their bits.
+pub const LSP_TOKEN_MODIFIERS: &[&str] = &["declaration"];
+
+/// The LSP token type for a class, if there is one that fits.
+fn lsp_type(class: &str) -> Option<&'static str> {
+    Some(match class {
+        "keyword1" | "keyword2" | "keyword3" | "quasi_keyword" | "improper"
+        | "literal" => "keyword",
+        "tfree" | "tvar" => "typeParameter",
+        "binding" => "function",
+        "free" | "skolem" | "var" | "dynamic_fact" => "variable",
+        "bound" => "parameter",
+        "operator" => "operator",
+        "inner_numeral" => "number",
+        "inner_quoted" | "inner_string" | "inner_cartouche" | "string" | "alt_string"
+        | "verbatim" | "cartouche" => "string",
+        "antiquoted" | "antiquote" => "macro",
+        "comment" | "comment1" | "comment2" | "comment3" => "comment",
+        _ => return None,
+    })
+}
+
+/// The LSP token modifier for a class, if there is one that fits.
+fn lsp_modifier(class: &str) -> Option<&'static str> {
+    match class {
+        "binding" => Some("declaration"),
+        _ => None,
+    }
+}
+
+/// A semantic token, before it is encoded relative to the one before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LspToken {
+    pub line: usize,
+    /// The start within the line, in UTF-16 code units.
+    pub start: usize,
+    pub length: usize,
+    /// The index of the type in [`LSP_TOKEN_TYPES`].
+    pub token_type: usize,
+    /// The bits of the modifiers in [`LSP_TOKEN_MODIFIERS`].
+    pub modifiers: u32,
+}
+
+/// The semantic tokens of the text, given as its lines. The type of a token comes
+/// from its innermost class that has one, and tokens with no such class are left
+/// out. Since LSP tokens can't span several lines, each line has tokens of its own.
+pub fn lsp_tokens(lines: &[Vec<TagTree<'_>>]) -> Vec<LspToken> {
+    let mut lsp_tokens: Vec<LspToken> = vec![];
+    for (line, nodes) in lines.iter().enumerate() {
+        for token in tokens(nodes) {
+            let token_type =
+                match token.classes.iter().rev().find_map(|cls| lsp_type(cls)) {
+                    Some(token_type) => token_type,
+                    None => continue,
+                };
+            let token_type = LSP_TOKEN_TYPES
+                .iter()
+                .position(|ty| *ty == token_type)
+                .unwrap();
+            let modifiers = token
+                .classes
+                .iter()
+                .filter_map(|cls| lsp_modifier(cls))
+                .map(|modifier| {
+                    let bit = LSP_TOKEN_MODIFIERS
+                        .iter()
+                        .position(|m| *m == modifier)
+                        .unwrap();
+                    1 << bit
+                })
+                .fold(0, |modifiers, bit| modifiers | bit);
+
+            match lsp_tokens.last_mut() {
+                Some(last)
+                    if last.line == line
+                        && last.start + last.length == token.offset
+                        && (last.token_type, last.modifiers)
+                            == (token_type, modifiers) =>
+                {
+                    last.length += token.length;
+                }
+                _ => lsp_tokens.push(LspToken {
+                    line,
+                    start: token.offset,
+                    length: token.length,
+                    token_type,
+                    modifiers,
+                }),
+            }
+        }
+    }
+    lsp_tokens
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Legend {
+    token_types: &'static [&'static str],
+    token_modifiers: &'static [&'static str],
+}
+
+#[derive(Serialize)]
+struct LspExport {
+    legend: Legend,
+    data: Vec<usize>,
+}
+
+/// Write the tokens as the `SemanticTokens` of LSP, with five numbers for each
+/// token, relative to the one before it, along with the legend for their types and
+/// modifiers.
+pub fn write_lsp(w: impl io::Write, tokens: &[LspToken]) -> serde_json::Result<()> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut line, mut start) = (0, 0);
+    for token in tokens {
+        if token.line != line {
+            start = 0;
+        }
+        data.extend([
+            token.line - line,
+            token.start - start,
+            token.length,
+            token.token_type,
+            token.modifiers as usize,
+        ]);
+        line = token.line;
+        start = token.start;
+    }
+
+    let export = LspExport {
+        legend: Legend {
+            token_types: LSP_TOKEN_TYPES,
+            token_modifiers: LSP_TOKEN_MODIFIERS,
+        },
+        data,
+    };
+    serde_json::to_writer(w, &export)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    /// If this test fails, the format has changed, and `SCHEMA_VERSION` along with
+    /// the schema in `schemas/` most likely need to be updated.
+    #[test]
+    fn json_format() {
+        let tokens = [Token {
+            offset: 3,
+            length: 2,
+            classes: vec!["free".to_owned()],
+        }];
+        let mut buf = Vec::new();
+        write_json(&mut buf, &tokens).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"{"schema_version":1,"tokens":[{"offset":3,"length":2,"classes":["free"]}]}"#
+        );
+    }
+
+    #[test]
+    fn tokens_merge_and_nest() {
+        let input = vec![
+            TagTree::Tag {
+                tag: Rc::new(Tag::SpanClass("keyword1 command".to_owned())),
+                children: vec![TagTree::Text("lem"), TagTree::Text("ma")],
+            },
+            TagTree::Text(" ‹"),
+            TagTree::Tag {
+                tag: Rc::new(Tag::Tooltip("tooltip".to_owned())),
+                children: vec![TagTree::Tag {
+                    tag: Rc::new(Tag::SpanClass("free".to_owned())),
+                    children: vec![TagTree::Text("x")],
+                }],
+            },
+        ];
+
+        assert_eq!(
+            tokens(&input),
+            [
+                Token {
+                    offset: 0,
+                    length: 5,
+                    classes: vec!["keyword1".to_owned(), "command".to_owned()],
+                },
+                Token {
+                    offset: 7,
+                    length: 1,
+                    classes: vec!["free".to_owned()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lsp_format() {
+        let span = |cls: &str, text| TagTree::Tag {
+            tag: Rc::new(Tag::SpanClass(cls.to_owned())),
+            children: vec![TagTree::Text(text)],
+        };
+        let lines = [
+            vec![
+                span("keyword1 command", "definition"),
+                TagTree::Text(" "),
+                TagTree::Tag {
+                    tag: Rc::new(Tag::SpanClass("binding".to_owned())),
+                    children: vec![span("free", "f")],
+                },
+                TagTree::Text(" "),
+                span("xml_elem", "where"),
+            ],
+            vec![TagTree::Text("  "), span("free", "f"), span("skolem", "x")],
+        ];
+        let tokens = lsp_tokens(&lines);
+        assert_eq!(
+            tokens[1],
+            LspToken {
+                line: 0,
+                start: 11,
+                length: 1,
+                token_type: 3,
+                modifiers: 1,
+            }
+        );
+
+        let mut buf = Vec::new();
+        write_lsp(&mut buf, &tokens).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["legend"]["tokenTypes"][0], "keyword");
+        assert_eq!(json["legend"]["tokenModifiers"][0], "declaration");
+        // The free variable and the skolem constant are both variables, so they make
+        // up a single token.
+        assert_eq!(
+            json["data"],
+            serde_json::json!([0, 0, 10, 0, 0, 0, 11, 1, 3, 1, 1, 2, 2, 3, 0])
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Parsing a large session dump into an arena [`Document`], compared with the
+//! regular tree built by [`yxml::parse`], by time and by number of allocations.
+//!
+//! Run with `cargo bench --bench arena`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use yxml::arena::Document;
+
+/// Counts the allocations made, to compare how many each representation needs.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A session of `commands` commands, each marked up the way Isabelle does, with
+/// positions on every command and entities and types on every token.
+fn session(commands: usize) -> String {
+    let mut yxml = String::new();
+    for i in 0..commands {
+        yxml.push_str(&format!(
+            "\x05\x06command\x06line={}\x06offset={}\x06id={}\x05",
+            i + 1,
+            i * 40,
+            i
+        ));
+        for token in 0..10 {
+            yxml.push_str(&format!(
+                "\x05\x06entity\x06ref={}\x06kind=constant\x06name=c{}\x05\
+                 \x05\x06typing\x05\x05\x06\x05\x05\x06xml_body\x05\
+                 \x05\x06free\x05x{}\x05\x06\x05\x05\x06\x05\x05\x06\x05 ",
+                token, token, token
+            ));
+        }
+        yxml.push_str("\n\x05\x06\x05");
+    }
+    yxml
+}
+
+/// The fastest of a few runs of `f`, and the number of allocations it makes.
+fn measure(f: impl Fn()) -> (Duration, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let elapsed = (0..10)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    (elapsed, allocations)
+}
+
+fn main() {
+    for &commands in &[1000, 10000, 100000] {
+        let input = session(commands);
+        let (tree, tree_allocations) = measure(|| {
+            black_box(yxml::parse(black_box(&input)).unwrap());
+        });
+        let (arena, arena_allocations) = measure(|| {
+            black_box(Document::parse(black_box(&input)).unwrap());
+        });
+        println!(
+            "{} commands ({} bytes): tree {:?} with {} allocations, \
+             arena {:?} with {} allocations",
+            commands,
+            input.len(),
+            tree,
+            tree_allocations,
+            arena,
+            arena_allocations
+        );
+    }
+}
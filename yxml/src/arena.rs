@@ -0,0 +1,302 @@
+//! A compact tree representation for large inputs.
+//!
+//! [`parse`](crate::parse) allocates a vector of children and of attributes for
+//! every tag, which adds up for whole sessions. A [`Document`] instead stores all
+//! nodes in one vector and all attributes in another, with the structure encoded by
+//! indices. Tag names and attribute keys are interned, so each takes up four bytes
+//! and can be compared without looking at the string.
+
+use crate::intern::{Interner, Name};
+use crate::{Attributes, ErrorKind, Node, ParseError, RawEvent, Reader};
+use std::convert::TryFrom;
+
+/// Marks the absence of a node in the links between nodes.
+const NONE: u32 = u32::MAX;
+
+/// Convert the position of a node or attribute to the index stored for it, if it
+/// fits and can't be mistaken for [`NONE`].
+fn index(position: usize) -> Option<u32> {
+    u32::try_from(position).ok().filter(|&index| index != NONE)
+}
+
+#[derive(Clone, Debug)]
+enum Kind<'a> {
+    Text(&'a str),
+    Tag {
+        name: Name,
+        /// Range within `Document::attrs`.
+        attrs_start: u32,
+        attrs_end: u32,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Entry<'a> {
+    kind: Kind<'a>,
+    first_child: u32,
+    last_child: u32,
+    next_sibling: u32,
+}
+
+/// A parsed YXML document, with all nodes stored in a single arena.
+#[derive(Clone, Debug)]
+pub struct Document<'a> {
+    /// The first entry is a tag without a name, whose children are the top-level
+    /// nodes.
+    entries: Vec<Entry<'a>>,
+    attrs: Vec<(Name, &'a str)>,
+    names: Interner<'a>,
+}
+
+/// A reference to a node within a [`Document`].
+#[derive(Clone, Copy, Debug)]
+pub struct NodeRef<'d, 'a> {
+    doc: &'d Document<'a>,
+    id: u32,
+}
+
+impl<'a> Document<'a> {
+    pub fn parse(input: &'a str) -> Result<Document<'a>, ParseError<'a>> {
+        let mut names = Interner::new();
+        let mut doc = Document {
+            entries: vec![Entry {
+                kind: Kind::Tag {
+                    name: names.intern(""),
+                    attrs_start: 0,
+                    attrs_end: 0,
+                },
+                first_child: NONE,
+                last_child: NONE,
+                next_sibling: NONE,
+            }],
+            attrs: vec![],
+            names,
+        };
+        let mut open = vec![0];
+        // Reused for every tag, so that its attributes aren't allocated separately.
+        let mut scratch = vec![];
+
+        let mut reader = Reader::new(input);
+        loop {
+            let start = reader.offset();
+            let event = match reader.next_raw(&mut scratch) {
+                Some(event) => event?,
+                None => break,
+            };
+            let too_large = || ParseError::new(ErrorKind::TooLarge, input, start);
+            let kind = match event {
+                RawEvent::End(_) => {
+                    open.pop();
+                    continue;
+                }
+                RawEvent::Text(text) => Kind::Text(text),
+                RawEvent::Start(name) => {
+                    let attrs_start = index(doc.attrs.len()).ok_or_else(too_large)?;
+                    for &(key, value) in &scratch {
+                        doc.attrs.push((doc.names.intern(key), value));
+                    }
+                    Kind::Tag {
+                        name: doc.names.intern(name),
+                        attrs_start,
+                        attrs_end: index(doc.attrs.len()).ok_or_else(too_large)?,
+                    }
+                }
+            };
+
+            let is_tag = matches!(kind, Kind::Tag { .. });
+            let id = index(doc.entries.len()).ok_or_else(too_large)?;
+            doc.entries.push(Entry {
+                kind,
+                first_child: NONE,
+                last_child: NONE,
+                next_sibling: NONE,
+            });
+
+            let parent = *open.last().unwrap() as usize;
+            match doc.entries[parent].last_child {
+                NONE => doc.entries[parent].first_child = id,
+                last => doc.entries[last as usize].next_sibling = id,
+            }
+            doc.entries[parent].last_child = id;
+
+            if is_tag {
+                open.push(id);
+            }
+        }
+
+        Ok(doc)
+    }
+
+    /// The top-level nodes.
+    pub fn roots(&self) -> Children<'_, 'a> {
+        Children {
+            doc: self,
+            next: self.entries[0].first_child,
+        }
+    }
+
+    /// The number of nodes in the document.
+    pub fn len(&self) -> usize {
+        self.entries.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The interned handle for a tag name or attribute key, if it occurs in the
+    /// document, for comparing against [`NodeRef::name_id`].
+    pub fn name_id(&self, name: &str) -> Option<Name> {
+        self.names.get(name)
+    }
+
+    /// Convert to the regular tree representation.
+    pub fn to_nodes(&self) -> Vec<Node<'a>> {
+        self.roots().map(|node| node.to_node()).collect()
+    }
+}
+
+impl<'d, 'a> NodeRef<'d, 'a> {
+    fn entry(&self) -> &'d Entry<'a> {
+        &self.doc.entries[self.id as usize]
+    }
+
+    /// The name of a tag, or `None` for text.
+    pub fn name(&self) -> Option<&'a str> {
+        self.name_id().map(|name| self.doc.names.resolve(name))
+    }
+
+    /// The interned name of a tag, or `None` for text.
+    pub fn name_id(&self) -> Option<Name> {
+        match self.entry().kind {
+            Kind::Text(_) => None,
+            Kind::Tag { name, .. } => Some(name),
+        }
+    }
+
+    /// The contents of a text node, or `None` for a tag.
+    pub fn text(&self) -> Option<&'a str> {
+        match self.entry().kind {
+            Kind::Text(text) => Some(text),
+            Kind::Tag { .. } => None,
+        }
+    }
+
+    fn raw_attrs(&self) -> &'d [(Name, &'a str)] {
+        match self.entry().kind {
+            Kind::Text(_) => &[],
+            Kind::Tag {
+                attrs_start,
+                attrs_end,
+                ..
+            } => &self.doc.attrs[attrs_start as usize..attrs_end as usize],
+        }
+    }
+
+    /// The attributes of a tag, in order. Text nodes have no attributes.
+    pub fn attrs(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'd {
+        let names = &self.doc.names;
+        self.raw_attrs()
+            .iter()
+            .map(move |&(key, value)| (names.resolve(key), value))
+    }
+
+    /// The value of an attribute of a tag. If it occurs multiple times, the last
+    /// occurrence is used, as with [`Attributes::get`].
+    pub fn attr(&self, key: &str) -> Option<&'a str> {
+        let key = self.doc.names.get(key)?;
+        self.raw_attrs()
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    pub fn children(&self) -> Children<'d, 'a> {
+        Children {
+            doc: self.doc,
+            next: self.entry().first_child,
+        }
+    }
+
+    /// Copy the subtree into the regular tree representation.
+    pub fn to_node(&self) -> Node<'a> {
+        match self.entry().kind {
+            Kind::Text(text) => Node::Text(text),
+            Kind::Tag { name, .. } => Node::Tag {
+                name: self.doc.names.resolve(name),
+                attrs: self.attrs().collect::<Attributes<'a>>(),
+                children: self.children().map(|child| child.to_node()).collect(),
+            },
+        }
+    }
+}
+
+/// Iterator over the children of a node in a [`Document`].
+#[derive(Clone, Debug)]
+pub struct Children<'d, 'a> {
+    doc: &'d Document<'a>,
+    next: u32,
+}
+
+impl<'d, 'a> Iterator for Children<'d, 'a> {
+    type Item = NodeRef<'d, 'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NONE {
+            return None;
+        }
+
+        let node = NodeRef {
+            doc: self.doc,
+            id: self.next,
+        };
+        self.next = node.entry().next_sibling;
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn same_as_tree() {
+        let input = "a\x05\x06b\x06k=v\x06l=w\x05\x05\x06c\x05x\x05\x06\x05y\x05\x06\x05\
+            \x05\x06d\x05\x05\x06\x05";
+        let doc = Document::parse(input).unwrap();
+        assert_eq!(doc.len(), 6);
+        assert_eq!(doc.to_nodes(), parse(input).unwrap());
+
+        let b = doc.roots().nth(1).unwrap();
+        assert_eq!(b.name(), Some("b"));
+        assert_eq!(b.name_id(), doc.name_id("b"));
+        assert_eq!(doc.name_id("x"), None);
+        assert_eq!(b.attr("l"), Some("w"));
+        let texts: Vec<_> = b.children().map(|child| child.text()).collect();
+        assert_eq!(texts, [None, Some("y")]);
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        let input = "\x05\x06a\x06k=1\x06k=2\x05\x05\x06\x05";
+        let doc = Document::parse(input).unwrap();
+        let a = doc.roots().next().unwrap();
+        assert_eq!(a.attr("k"), Some("2"));
+        assert_eq!(a.attr("k"), a.to_node().attr("k"));
+        assert_eq!(a.attrs().count(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Document::parse("\x05\x06a\x05").is_err());
+    }
+
+    #[test]
+    fn index_limit() {
+        assert_eq!(index(7), Some(7));
+        assert_eq!(index(NONE as usize), None);
+        assert_eq!(index(usize::MAX), None);
+    }
+}
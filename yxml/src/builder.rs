@@ -0,0 +1,86 @@
+//! A builder for constructing trees by hand, as in tests or tools that synthesize
+//! markup.
+
+use crate::{Attributes, Node};
+
+/// A tag under construction, as started by [`Node::tag`]. Builders can be nested
+/// directly, and are turned into a [`Node`] with [`TagBuilder::build`] or `into()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[must_use]
+pub struct TagBuilder<'a> {
+    name: &'a str,
+    attrs: Attributes<'a>,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    /// Start building a tag with the given name.
+    pub fn tag(name: &'a str) -> TagBuilder<'a> {
+        TagBuilder {
+            name,
+            attrs: Attributes::new(),
+            children: vec![],
+        }
+    }
+}
+
+impl<'a> TagBuilder<'a> {
+    pub fn attr(mut self, key: &'a str, value: &'a str) -> Self {
+        self.attrs.push(key, value);
+        self
+    }
+
+    pub fn child(mut self, child: impl Into<Node<'a>>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+
+    pub fn children<I>(mut self, children: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Node<'a>>,
+    {
+        self.children.extend(children.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn build(self) -> Node<'a> {
+        Node::Tag {
+            name: self.name,
+            attrs: self.attrs,
+            children: self.children,
+        }
+    }
+}
+
+impl<'a> From<TagBuilder<'a>> for Node<'a> {
+    fn from(builder: TagBuilder<'a>) -> Self {
+        builder.build()
+    }
+}
+
+impl<'a> From<&'a str> for Node<'a> {
+    fn from(text: &'a str) -> Self {
+        Node::Text(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_string;
+
+    #[test]
+    fn nested() {
+        let node = Node::tag("xml_elem")
+            .attr("xml_name", "typing")
+            .child(Node::tag("xml_body").child("'a"))
+            .children(["x", "y"])
+            .build();
+        assert_eq!(
+            to_string(&[node]).unwrap(),
+            "\x05\x06xml_elem\x06xml_name=typing\x05\
+             \x05\x06xml_body\x05'a\x05\x06\x05xy\x05\x06\x05"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Splitting a stream of messages in Isabelle's byte message format, as used by
+//! PIDE, into the YXML documents they contain.
+//!
+//! Each message starts with a header line listing the lengths of its chunks in
+//! bytes, separated by commas, and the chunks follow directly after it. For
+//! example, `"2,3\nabxyz"` is a message with the chunks `"ab"` and `"xyz"`.
+
+use std::io::{self, BufRead, Read};
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the chunks of the next message, or return `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 {
+        return Ok(None);
+    }
+
+    let header = header.trim_end_matches(['\n', '\r']);
+    let lengths = header
+        .split(',')
+        .map(|length| length.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid_data(format!("malformed message header {:?}", header)))?;
+
+    let mut chunks = Vec::with_capacity(lengths.len());
+    for length in lengths {
+        // Not allocated up front, as the header may claim more than is there.
+        let mut chunk = vec![];
+        reader
+            .by_ref()
+            .take(length as u64)
+            .read_to_end(&mut chunk)?;
+        if chunk.len() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("message chunk of {} bytes cut off", length),
+            ));
+        }
+        chunks.push(chunk);
+    }
+    Ok(Some(chunks))
+}
+
+/// An iterator over the messages in a stream, as returned by [`messages`].
+#[derive(Debug)]
+pub struct Messages<R> {
+    reader: R,
+}
+
+/// Iterate over the messages in `reader`, each as the list of its chunks.
+pub fn messages<R: BufRead>(reader: R) -> Messages<R> {
+    Messages { reader }
+}
+
+impl<R: BufRead> Iterator for Messages<R> {
+    type Item = io::Result<Vec<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_message(&mut self.reader).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_bytes, Node};
+
+    #[test]
+    fn split_messages() {
+        let input: &[u8] = b"2,7\r\nab\x05\x06a\x05\x05\x06\x050\n";
+        let messages: Vec<_> = messages(input).collect::<io::Result<_>>().unwrap();
+        assert_eq!(
+            messages,
+            [
+                vec![b"ab".to_vec(), b"\x05\x06a\x05\x05\x06\x05".to_vec()],
+                vec![vec![]]
+            ]
+        );
+
+        let mut buf = String::new();
+        assert_eq!(
+            parse_bytes(&messages[0][1], &mut buf).unwrap(),
+            [Node::tag("a").build()]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let err = read_message(&mut &b"2,x\nabc"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_message(&mut &b"5\nabc"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = read_message(&mut &b"99999999999999999\nabc"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,259 @@
+//! Comparing two trees, as for seeing how the markup of a theory changed between
+//! revisions.
+
+use crate::{Attributes, Node};
+
+/// A difference between two trees, as found by [`diff`].
+///
+/// A path lists the index of each node on the way down from the top level. Paths of
+/// removed nodes refer to the old tree, and all other paths to the new one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit<'n, 'a> {
+    /// A text node whose contents changed.
+    Text {
+        path: Vec<usize>,
+        old: &'a str,
+        new: &'a str,
+    },
+    /// A tag whose attributes changed. Its children are compared separately.
+    Attributes {
+        path: Vec<usize>,
+        old: &'n Attributes<'a>,
+        new: &'n Attributes<'a>,
+    },
+    /// A subtree only present in the new tree.
+    Added {
+        path: Vec<usize>,
+        node: &'n Node<'a>,
+    },
+    /// A subtree only present in the old tree.
+    Removed {
+        path: Vec<usize>,
+        node: &'n Node<'a>,
+    },
+}
+
+/// List the edits that turn the nodes `old` into `new`, in document order.
+///
+/// Nodes are paired up between the trees by the longest common subsequence of the
+/// children of each tag, where text only pairs up with text, and tags with tags of
+/// the same name. Paired nodes are then compared recursively.
+///
+/// Finding the subsequence takes time and memory proportional to the product of
+/// the numbers of children that differ, after leaving out the ones the lists of
+/// children start and end with in common. If that would take more than 64 MiB,
+/// those children are listed as removed and added instead of being paired up.
+pub fn diff<'n, 'a>(old: &'n [Node<'a>], new: &'n [Node<'a>]) -> Vec<Edit<'n, 'a>> {
+    let mut edits = vec![];
+    diff_children(old, new, &mut vec![], &mut vec![], &mut edits);
+    edits
+}
+
+/// The most entries of the table for the longest common subsequence of two lists of
+/// children, which take up four bytes each.
+const MAX_TABLE: usize = 1 << 24;
+
+fn pairable(a: &Node<'_>, b: &Node<'_>) -> bool {
+    a.name() == b.name()
+}
+
+fn with_index(path: &[usize], index: usize) -> Vec<usize> {
+    let mut path = path.to_vec();
+    path.push(index);
+    path
+}
+
+fn diff_children<'n, 'a>(
+    old: &'n [Node<'a>],
+    new: &'n [Node<'a>],
+    old_path: &mut Vec<usize>,
+    new_path: &mut Vec<usize>,
+    edits: &mut Vec<Edit<'n, 'a>>,
+) {
+    // Revisions tend to differ in few places, so skip the common prefix and suffix
+    // before doing the quadratic work.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // lcs[i * (m + 1) + j] is the length of the longest common subsequence of
+    // a[i..] and b[j..].
+    let (n, m) = (a.len(), b.len());
+    let size = match (n + 1).checked_mul(m + 1).filter(|&size| size <= MAX_TABLE) {
+        Some(size) => size,
+        None => {
+            for (i, node) in a.iter().enumerate() {
+                let path = with_index(old_path, prefix + i);
+                edits.push(Edit::Removed { path, node });
+            }
+            for (j, node) in b.iter().enumerate() {
+                let path = with_index(new_path, prefix + j);
+                edits.push(Edit::Added { path, node });
+            }
+            return;
+        }
+    };
+    let mut lcs = vec![0u32; size];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if pairable(&a[i], &b[j]) {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n
+            && j < m
+            && pairable(&a[i], &b[j])
+            && lcs[at(i, j)] == lcs[at(i + 1, j + 1)] + 1
+        {
+            old_path.push(prefix + i);
+            new_path.push(prefix + j);
+            diff_nodes(&a[i], &b[j], old_path, new_path, edits);
+            old_path.pop();
+            new_path.pop();
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[at(i + 1, j)] >= lcs[at(i, j + 1)]) {
+            edits.push(Edit::Removed {
+                path: with_index(old_path, prefix + i),
+                node: &a[i],
+            });
+            i += 1;
+        } else {
+            edits.push(Edit::Added {
+                path: with_index(new_path, prefix + j),
+                node: &b[j],
+            });
+            j += 1;
+        }
+    }
+}
+
+fn diff_nodes<'n, 'a>(
+    old: &'n Node<'a>,
+    new: &'n Node<'a>,
+    old_path: &mut Vec<usize>,
+    new_path: &mut Vec<usize>,
+    edits: &mut Vec<Edit<'n, 'a>>,
+) {
+    match (old, new) {
+        (Node::Text(old), Node::Text(new)) => {
+            if old != new {
+                edits.push(Edit::Text {
+                    path: new_path.clone(),
+                    old,
+                    new,
+                });
+            }
+        }
+        (
+            Node::Tag {
+                attrs: old_attrs,
+                children: old_children,
+                ..
+            },
+            Node::Tag {
+                attrs: new_attrs,
+                children: new_children,
+                ..
+            },
+        ) => {
+            if old_attrs != new_attrs {
+                edits.push(Edit::Attributes {
+                    path: new_path.clone(),
+                    old: old_attrs,
+                    new: new_attrs,
+                });
+            }
+            diff_children(old_children, new_children, old_path, new_path, edits);
+        }
+        _ => unreachable!("only nodes of the same kind are paired up"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn edits() {
+        let old = parse(
+            "\x05\x06a\x06k=v\x05x\x05\x06\x05\
+             \x05\x06b\x05\x05\x06c\x05\x05\x06\x05y\x05\x06\x05",
+        )
+        .unwrap();
+        let new = parse(
+            "\x05\x06a\x06k=w\x05z\x05\x06\x05\
+             \x05\x06b\x05y\x05\x06d\x05\x05\x06\x05\x05\x06\x05",
+        )
+        .unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Edit::Attributes {
+                    path: vec![0],
+                    old: &[("k", "v")].iter().copied().collect(),
+                    new: &[("k", "w")].iter().copied().collect(),
+                },
+                Edit::Text {
+                    path: vec![0, 0],
+                    old: "x",
+                    new: "z",
+                },
+                Edit::Removed {
+                    path: vec![1, 0],
+                    node: &Node::tag("c").build(),
+                },
+                Edit::Added {
+                    path: vec![1, 1],
+                    node: &Node::tag("d").build(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_children() {
+        let old: Vec<_> = (0..5000).map(|_| Node::Text("x")).collect();
+        let mut new = old.clone();
+        new[0] = Node::Text("y");
+        new[4999] = Node::Text("y");
+        new.push(Node::tag("a").build());
+
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 5000 + 5001);
+        assert_eq!(
+            edits[0],
+            Edit::Removed {
+                path: vec![0],
+                node: &Node::Text("x"),
+            }
+        );
+        assert_eq!(
+            edits[5000],
+            Edit::Added {
+                path: vec![0],
+                node: &Node::Text("y"),
+            }
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let nodes = parse("a\x05\x06b\x05c\x05\x06\x05").unwrap();
+        assert_eq!(diff(&nodes, &nodes), []);
+    }
+}
@@ -0,0 +1,276 @@
+//! Incremental parsing of input that arrives in pieces, like a dump streamed over
+//! the network.
+
+use crate::{ErrorKind, Event, OwnedNode, ParseError, Reader};
+#[cfg(feature = "tokio")]
+use std::io;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A parser that is fed input in chunks, and returns each top-level node as soon as
+/// it is complete.
+///
+/// The input is only read once, and only the part after the last complete tag
+/// delimiter or text is kept around, along with the tags that are still open.
+/// Memory use thus depends on the size of the largest top-level node, rather than
+/// that of the whole input. Error positions refer to the input as a whole.
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    /// The input that hasn't been read yet, after the first `read` bytes, which are
+    /// only dropped on the next call, as an error may borrow from them until then.
+    buf: String,
+    read: usize,
+    /// Where the unread input starts within the whole input.
+    position: Position,
+    /// The tags that are open, outermost first.
+    open: Vec<OpenTag>,
+}
+
+/// A position within the whole input.
+#[derive(Clone, Copy, Debug, Default)]
+struct Position {
+    offset: usize,
+    /// Lines before the position, minus 1.
+    line: usize,
+    /// Characters between the last line break and the position.
+    column: usize,
+}
+
+impl Position {
+    fn advance(&mut self, text: &str) {
+        self.offset += text.len();
+        match text.rfind('\n') {
+            Some(i) => {
+                self.line += text.matches('\n').count();
+                self.column = text[i + 1..].chars().count();
+            }
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    /// Make the position of an error in input starting here refer to the whole input.
+    fn adjust<'s>(&self, mut error: ParseError<'s>) -> ParseError<'s> {
+        error.offset += self.offset;
+        if error.line == 1 {
+            error.column += self.column;
+        }
+        error.line += self.line;
+        error
+    }
+}
+
+/// A tag whose end hasn't been read yet.
+#[derive(Clone, Debug)]
+struct OpenTag {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<OwnedNode>,
+    /// Where the tag starts, for reporting it if it is never closed.
+    position: Position,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `chunk` to the buffer, read as much of it as possible, and return the
+    /// top-level nodes that were completed. Unless `at_end`, text that reaches the
+    /// end of the buffer isn't read, as it may continue in the next chunk.
+    fn read(
+        &mut self,
+        chunk: &str,
+        at_end: bool,
+    ) -> Result<Vec<OwnedNode>, ParseError<'_>> {
+        self.buf.drain(..self.read);
+        self.read = 0;
+        self.buf.push_str(chunk);
+
+        let mut reader = Reader::new(&self.buf).with_outer_tags(self.open.len());
+        let mut position = self.position;
+        let mut nodes = vec![];
+        loop {
+            let start = reader.offset();
+            let node = match reader.next() {
+                None => break,
+                Some(Ok(Event::Start { name, attrs })) => {
+                    self.open.push(OpenTag {
+                        name: name.to_owned(),
+                        attrs: attrs
+                            .iter()
+                            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                            .collect(),
+                        children: vec![],
+                        position,
+                    });
+                    None
+                }
+                Some(Ok(Event::Text(_)))
+                    if !at_end && reader.offset() == self.buf.len() =>
+                {
+                    break
+                }
+                Some(Ok(Event::Text(text))) => Some(OwnedNode::Text(text.to_owned())),
+                Some(Ok(Event::End(_))) => {
+                    let tag = self.open.pop().unwrap();
+                    Some(OwnedNode::Tag {
+                        name: tag.name,
+                        attrs: tag.attrs,
+                        children: tag.children,
+                    })
+                }
+                // These only mean that the input is incomplete so far.
+                Some(Err(ParseError {
+                    kind: ErrorKind::NoClosingX | ErrorKind::UnclosedTag(_),
+                    ..
+                })) if !at_end => break,
+                Some(Err(error)) => return Err(self.position.adjust(error)),
+            };
+            if let Some(node) = node {
+                match self.open.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => nodes.push(node),
+                }
+            }
+            position.advance(&self.buf[start..reader.offset()]);
+            self.read = reader.offset();
+        }
+        self.position = position;
+
+        if let (true, Some(tag)) = (at_end, self.open.last()) {
+            return Err(ParseError {
+                kind: ErrorKind::UnclosedTag(&tag.name),
+                offset: tag.position.offset,
+                line: tag.position.line + 1,
+                column: tag.position.column + 1,
+            });
+        }
+        Ok(nodes)
+    }
+
+    /// Add a chunk of input, and return the top-level nodes it completes. Text at
+    /// the top level is only returned once it is followed by a tag, as it may
+    /// continue in the next chunk.
+    ///
+    /// After an error, the parser is left in an unspecified state.
+    pub fn feed(&mut self, chunk: &str) -> Result<Vec<OwnedNode>, ParseError<'_>> {
+        self.read(chunk, false)
+    }
+
+    /// Signal the end of the input, and return the remaining nodes.
+    pub fn finish(&mut self) -> Result<Vec<OwnedNode>, ParseError<'_>> {
+        self.read("", true)
+    }
+}
+
+/// Parse YXML read from `reader` as it arrives, without first buffering the whole
+/// input. Invalid UTF-8 and parse errors are reported as
+/// [`io::ErrorKind::InvalidData`].
+#[cfg(feature = "tokio")]
+pub async fn parse_from_async_reader<R>(mut reader: R) -> io::Result<Vec<OwnedNode>>
+where
+    R: AsyncRead + Unpin,
+{
+    fn invalid_data(error: impl ToString) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+    }
+
+    let mut parser = Parser::new();
+    let mut nodes = vec![];
+    // Bytes read but not fed to the parser yet, as they end in the middle of a
+    // character.
+    let mut pending = vec![];
+    let mut chunk = vec![0; 64 * 1024];
+    loop {
+        let len = reader.read(&mut chunk).await?;
+        if len == 0 {
+            break;
+        }
+
+        pending.extend_from_slice(&chunk[..len]);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(error) => return Err(invalid_data(error)),
+        };
+        let text = std::str::from_utf8(&pending[..valid]).unwrap();
+        nodes.extend(parser.feed(text).map_err(invalid_data)?);
+        pending.drain(..valid);
+    }
+
+    if let Err(error) = std::str::from_utf8(&pending) {
+        return Err(invalid_data(error));
+    }
+    nodes.extend(parser.finish().map_err(invalid_data)?);
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn chunks() {
+        let input = "a\x05\x06b\x06k=v\x05\x05\x06c\x05x\x05\x06\x05\x05\x06\x05\nd\x05\x06e\x05\x05\x06\x05";
+        let expected: Vec<OwnedNode> = parse(input)
+            .unwrap()
+            .into_iter()
+            .map(OwnedNode::from)
+            .collect();
+        for size in 1..input.len() {
+            let mut parser = Parser::new();
+            let mut nodes = vec![];
+            for chunk in input.as_bytes().chunks(size) {
+                nodes.extend(parser.feed(std::str::from_utf8(chunk).unwrap()).unwrap());
+            }
+            nodes.extend(parser.finish().unwrap());
+            assert_eq!(nodes, expected, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn error_positions() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("x\n\x05\x06a\x05\x05\x06\x05y").unwrap().len(),
+            2
+        );
+        let err = parser.feed("\x05\x06\x05").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnmatchedClosingTag);
+        assert_eq!((err.offset, err.line, err.column), (10, 2, 9));
+
+        let mut parser = Parser::new();
+        parser.feed("\x05\x06a\x05").unwrap();
+        let err = parser.finish().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnclosedTag("a"));
+
+        // The tag was opened in an earlier chunk.
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed("x\n\x05\x06a\x05").unwrap().len(), 1);
+        assert!(parser.feed("y").unwrap().is_empty());
+        let err = parser.finish().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnclosedTag("a"));
+        assert_eq!((err.offset, err.line, err.column), (2, 2, 1));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_reader() {
+        let input = "α\x05\x06b\x06k=β\x05γ\x05\x06\x05";
+        // Split in the middle of characters.
+        let bytes = input.as_bytes();
+        let reader = bytes[..1].chain(&bytes[1..8]).chain(&bytes[8..]);
+        let expected: Vec<OwnedNode> = parse(input)
+            .unwrap()
+            .into_iter()
+            .map(OwnedNode::from)
+            .collect();
+        assert_eq!(parse_from_async_reader(reader).await.unwrap(), expected);
+
+        let err = parse_from_async_reader(&b"\x05\x06a\x05\xff"[..])
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
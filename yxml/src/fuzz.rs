@@ -0,0 +1,103 @@
+//! Random trees for fuzzing tools that consume YXML, with the `arbitrary` feature.
+//!
+//! The trees are exactly those that survive a round trip through [`to_string`]
+//! and [`parse`]: names and attribute keys are never empty, nothing contains the
+//! delimiter characters, and there are no empty or adjacent text nodes.
+//!
+//! [`to_string`]: crate::to_string
+//! [`parse`]: crate::parse
+
+use crate::{Attributes, Node, X, Y};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// How deep generated tags nest, to keep recursion in check.
+const MAX_DEPTH: usize = 8;
+
+/// A string taken from the input, cut off before the first character that can't
+/// appear in it.
+fn string<'a>(u: &mut Unstructured<'a>, forbidden: &[char]) -> Result<&'a str> {
+    let s = <&'a str>::arbitrary(u)?;
+    let end = s
+        .find(|c| c == X || c == Y || forbidden.contains(&c))
+        .unwrap_or(s.len());
+    Ok(&s[..end])
+}
+
+fn non_empty(s: &str) -> &str {
+    if s.is_empty() {
+        "a"
+    } else {
+        s
+    }
+}
+
+fn tag<'a>(u: &mut Unstructured<'a>, depth: usize) -> Result<Node<'a>> {
+    let name = non_empty(string(u, &[])?);
+    let mut attrs = Attributes::new();
+    for _ in 0..u.int_in_range(0..=3)? {
+        let key = non_empty(string(u, &['='])?);
+        attrs.push(key, string(u, &[])?);
+    }
+
+    let children = if depth < MAX_DEPTH {
+        nodes_at(u, depth + 1)?
+    } else {
+        vec![]
+    };
+    Ok(Node::Tag {
+        name,
+        attrs,
+        children,
+    })
+}
+
+fn nodes_at<'a>(u: &mut Unstructured<'a>, depth: usize) -> Result<Vec<Node<'a>>> {
+    let mut nodes = vec![];
+    for _ in 0..u.int_in_range(0..=4)? {
+        let after_text = matches!(nodes.last(), Some(Node::Text(_)));
+        if !after_text && u.arbitrary()? {
+            let text = string(u, &[])?;
+            if !text.is_empty() {
+                nodes.push(Node::Text(text));
+            }
+        } else {
+            nodes.push(tag(u, depth)?);
+        }
+    }
+    Ok(nodes)
+}
+
+/// A random sequence of nodes, as found at the top level of a document.
+pub fn nodes<'a>(u: &mut Unstructured<'a>) -> Result<Vec<Node<'a>>> {
+    nodes_at(u, 0)
+}
+
+/// Generates a single tag. Use [`nodes`] for a whole document.
+impl<'a> Arbitrary<'a> for Node<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        tag(u, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, to_string};
+
+    #[test]
+    fn round_trip() {
+        // A linear congruential generator is enough to get varied inputs.
+        let mut state = 1u64;
+        for _ in 0..500 {
+            let data: Vec<u8> = (0..512)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    (state >> 56) as u8
+                })
+                .collect();
+            let nodes = nodes(&mut Unstructured::new(&data)).unwrap();
+            let yxml = to_string(&nodes).unwrap();
+            assert_eq!(parse(&yxml).unwrap(), nodes);
+        }
+    }
+}
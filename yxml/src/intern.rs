@@ -0,0 +1,72 @@
+//! Interning of tag and attribute names, which come from a small set but occur on
+//! every node.
+
+use std::collections::HashMap;
+
+/// A handle to an interned string. Handles from the same [`Interner`] are equal if
+/// and only if the strings are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Name(u32);
+
+/// A table of interned strings.
+#[derive(Clone, Debug, Default)]
+pub struct Interner<'a> {
+    strings: Vec<&'a str>,
+    names: HashMap<&'a str, Name>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The handle for `s`, adding it to the table if needed.
+    pub fn intern(&mut self, s: &'a str) -> Name {
+        let strings = &mut self.strings;
+        *self.names.entry(s).or_insert_with(|| {
+            strings.push(s);
+            Name(strings.len() as u32 - 1)
+        })
+    }
+
+    /// The handle for `s`, if it has been interned.
+    pub fn get(&self, s: &str) -> Option<Name> {
+        self.names.get(s).copied()
+    }
+
+    /// The string a handle stands for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle comes from a different interner.
+    pub fn resolve(&self, name: Name) -> &'a str {
+        self.strings[name.0 as usize]
+    }
+
+    /// The number of distinct strings interned.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern() {
+        let mut interner = Interner::new();
+        let a = interner.intern("a");
+        let b = interner.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("a"), a);
+        assert_eq!(interner.get("b"), Some(b));
+        assert_eq!(interner.get("c"), None);
+        assert_eq!(interner.resolve(b), "b");
+        assert_eq!(interner.len(), 2);
+    }
+}
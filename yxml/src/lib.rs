@@ -1,4 +1,31 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::Index;
+
+pub mod arena;
+mod builder;
+pub mod chunks;
+mod diff;
+mod feed;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod intern;
+mod mutate;
+mod parallel;
+mod select;
+mod visit;
+mod xml;
+
+pub use builder::TagBuilder;
+pub use diff::{diff, Edit};
+#[cfg(feature = "tokio")]
+pub use feed::parse_from_async_reader;
+pub use feed::Parser;
+pub use mutate::retain_recursive;
+pub use parallel::parse_files;
+pub use select::{Selector, SelectorError};
+pub use visit::Visit;
+pub use xml::{from_xml, XmlError};
 
 /// A node of the parsed YXML tree
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -6,121 +33,889 @@ pub enum Node<'a> {
     Text(&'a str),
     Tag {
         name: &'a str,
-        attrs: HashMap<&'a str, &'a str>,
+        attrs: Attributes<'a>,
         children: Vec<Node<'a>>,
     },
 }
 
+/// The attributes of a tag, in the order they appear in the input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Attributes<'a> {
+    pub fn new() -> Self {
+        Attributes(Vec::new())
+    }
+
+    /// The value of the attribute `key`. If it occurs multiple times, the last
+    /// occurrence is used, as with a map the attributes are inserted into in order.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    /// The values of all occurrences of the attribute `key`, in order.
+    pub fn get_all<'s>(&'s self, key: &'s str) -> impl Iterator<Item = &'a str> + 's {
+        self.0
+            .iter()
+            .filter(move |(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn push(&mut self, key: &'a str, value: &'a str) {
+        self.0.push((key, value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[(&'a str, &'a str)] {
+        &self.0
+    }
+}
+
+impl<'a> Index<&str> for Attributes<'a> {
+    type Output = str;
+
+    /// Panics if the attribute is missing.
+    fn index(&self, key: &str) -> &str {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("missing attribute {:?}", key),
+        }
+    }
+}
+
+impl<'a> From<Vec<(&'a str, &'a str)>> for Attributes<'a> {
+    fn from(attrs: Vec<(&'a str, &'a str)>) -> Self {
+        Attributes(attrs)
+    }
+}
+
+impl<'a> std::iter::FromIterator<(&'a str, &'a str)> for Attributes<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        Attributes(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for Attributes<'a> {
+    type Item = (&'a str, &'a str);
+    type IntoIter = std::vec::IntoIter<(&'a str, &'a str)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> Node<'a> {
+    /// The name of a tag, or `None` for text.
+    pub fn name(&self) -> Option<&'a str> {
+        match self {
+            Node::Text(_) => None,
+            Node::Tag { name, .. } => Some(name),
+        }
+    }
+
+    /// The value of an attribute of a tag. Text nodes have no attributes.
+    pub fn attr(&self, key: &str) -> Option<&'a str> {
+        match self {
+            Node::Text(_) => None,
+            Node::Tag { attrs, .. } => attrs.get(key),
+        }
+    }
+
+    /// All the text within this node, concatenated in document order.
+    pub fn text_content(&self) -> String {
+        self.walk()
+            .filter_map(|(_, node)| match node {
+                Node::Text(text) => Some(*text),
+                Node::Tag { .. } => None,
+            })
+            .collect()
+    }
+
+    /// The source text that this node was generated from. This is the text content
+    /// without the `xml_body` tags, whose contents Isabelle generates, e.g. to give
+    /// the type of a term. Symbols stay as `\<...>` sequences, as in the input.
+    pub fn to_source_text(&self) -> String {
+        let mut text = String::new();
+        self.push_source_text(&mut text);
+        text
+    }
+
+    fn push_source_text(&self, out: &mut String) {
+        match self {
+            Node::Text(text) => out.push_str(text),
+            Node::Tag {
+                name: "xml_body", ..
+            } => (),
+            Node::Tag { children, .. } => children
+                .iter()
+                .for_each(|child| child.push_source_text(out)),
+        }
+    }
+
+    /// The children of a tag. Text nodes have no children.
+    pub fn children(&self) -> &[Node<'a>] {
+        match self {
+            Node::Text(_) => &[],
+            Node::Tag { children, .. } => children,
+        }
+    }
+
+    /// The children which are tags with the given name.
+    pub fn children_named<'s>(
+        &'s self,
+        name: &'s str,
+    ) -> impl Iterator<Item = &'s Node<'a>> + 's {
+        self.children().iter().filter(move |child| {
+            matches!(child, Node::Tag { name: child_name, .. } if *child_name == name)
+        })
+    }
+
+    /// A depth-first traversal of the tree rooted at this node, in document order.
+    /// Each node is paired with its depth, with the root at depth 0.
+    pub fn walk(&self) -> Walk<'_, 'a> {
+        Walk {
+            stack: vec![std::slice::from_ref(self).iter()],
+        }
+    }
+
+    /// All the nodes below this one, in document order.
+    pub fn descendants(&self) -> impl Iterator<Item = &Node<'a>> + '_ {
+        self.walk().skip(1).map(|(_, node)| node)
+    }
+}
+
+/// Text longer than this many characters is truncated by the `Display` impl.
+const DISPLAY_TEXT_LIMIT: usize = 60;
+
+/// An indented outline of the tree, with one node per line, meant for debugging.
+/// Tags are shown with their attributes, and text is quoted, with long text
+/// truncated.
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, node) in self.walk() {
+            write!(f, "{:width$}", "", width = 2 * depth)?;
+            match node {
+                Node::Text(text) if text.chars().count() > DISPLAY_TEXT_LIMIT => {
+                    let truncated: String =
+                        text.chars().take(DISPLAY_TEXT_LIMIT).collect();
+                    writeln!(f, "{:?}...", truncated)?;
+                }
+                Node::Text(text) => writeln!(f, "{:?}", text)?,
+                Node::Tag { name, attrs, .. } => {
+                    write!(f, "{}", name)?;
+                    for (key, value) in attrs.iter() {
+                        write!(f, " {}={:?}", key, value)?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Format a sequence of trees with the `Display` impl of [`Node`].
+pub fn dump_tree(nodes: &[Node<'_>]) -> String {
+    nodes.iter().map(|node| node.to_string()).collect()
+}
+
+/// Iterator returned by [`Node::walk`].
+#[derive(Clone, Debug)]
+pub struct Walk<'n, 'a> {
+    stack: Vec<std::slice::Iter<'n, Node<'a>>>,
+}
+
+impl<'n, 'a> Iterator for Walk<'n, 'a> {
+    type Item = (usize, &'n Node<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let iter = self.stack.last_mut()?;
+            match iter.next() {
+                Some(node) => {
+                    let depth = self.stack.len() - 1;
+                    self.stack.push(node.children().iter());
+                    return Some((depth, node));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// A node that owns its contents, and can thus outlive the input it was parsed from,
+/// or be sent across threads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OwnedNode {
+    Text(String),
+    Tag {
+        name: String,
+        attrs: Vec<(String, String)>,
+        children: Vec<OwnedNode>,
+    },
+}
+
+impl<'a> Node<'a> {
+    /// Copy the tree into one that doesn't borrow from the input.
+    pub fn into_owned(self) -> OwnedNode {
+        match self {
+            Node::Text(text) => OwnedNode::Text(text.to_owned()),
+            Node::Tag {
+                name,
+                attrs,
+                children,
+            } => OwnedNode::Tag {
+                name: name.to_owned(),
+                attrs: attrs
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect(),
+                children: children.into_iter().map(Node::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl OwnedNode {
+    /// Build a borrowed view of the tree, for use with APIs that take a [`Node`].
+    pub fn borrow(&self) -> Node<'_> {
+        match self {
+            OwnedNode::Text(text) => Node::Text(text),
+            OwnedNode::Tag {
+                name,
+                attrs,
+                children,
+            } => Node::Tag {
+                name,
+                attrs: attrs.iter().map(|(k, v)| (&k[..], &v[..])).collect(),
+                children: children.iter().map(OwnedNode::borrow).collect(),
+            },
+        }
+    }
+}
+
+impl From<Node<'_>> for OwnedNode {
+    fn from(node: Node<'_>) -> Self {
+        node.into_owned()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
-pub enum ParseError<'a> {
+pub enum ErrorKind<'a> {
     UnclosedTag(&'a str),
     NoClosingX,
     UnexpectedContentBeforeAttributes,
     MissingName,
     MalformedAttribute,
     UnmatchedClosingTag,
+    /// A tag is nested deeper than the configured limit.
+    TooDeep,
+    /// A tag has the same attribute more than once, which is only reported when
+    /// enabled with [`Reader::reject_duplicate_attributes`].
+    DuplicateAttribute(&'a str),
+    /// An [`arena::Document`] would have more nodes or attributes than its 32-bit
+    /// indices can address.
+    TooLarge,
 }
 
-const X: char = '\x05';
-const Y: char = '\x06';
+impl fmt::Display for ErrorKind<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnclosedTag(name) => write!(f, "unclosed tag {:?}", name),
+            ErrorKind::NoClosingX => write!(f, "tag delimiter is never closed"),
+            ErrorKind::UnexpectedContentBeforeAttributes => {
+                write!(f, "unexpected content before tag name")
+            }
+            ErrorKind::MissingName => write!(f, "tag without a name"),
+            ErrorKind::MalformedAttribute => write!(f, "attribute without '='"),
+            ErrorKind::UnmatchedClosingTag => {
+                write!(f, "closing tag without a matching tag")
+            }
+            ErrorKind::TooDeep => write!(f, "tags nested too deeply"),
+            ErrorKind::DuplicateAttribute(key) => {
+                write!(f, "attribute {:?} given more than once", key)
+            }
+            ErrorKind::TooLarge => write!(f, "too many nodes for an arena document"),
+        }
+    }
+}
 
-type ParseResult<'a, T> = Result<(T, &'a str), ParseError<'a>>;
+/// A parse error, along with the position in the input where it occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError<'a> {
+    pub kind: ErrorKind<'a>,
+    /// Byte offset into the input. For an unclosed tag, this points to the start of
+    /// the tag, and otherwise, to the start of the offending tag delimiter.
+    pub offset: usize,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column in characters, starting at 1.
+    pub column: usize,
+}
 
-pub fn parse<'input>(
-    mut input: &'input str,
-) -> Result<Vec<Node<'input>>, ParseError<'input>> {
-    let mut nodes = Vec::new();
-    while !input.is_empty() {
-        let (node, rest) = Node::from_str(input)?;
-        input = rest;
-        nodes.push(node.ok_or(ParseError::UnmatchedClosingTag)?);
+impl<'a> ParseError<'a> {
+    fn new(kind: ErrorKind<'a>, input: &str, offset: usize) -> Self {
+        Lines::new().error(kind, input, offset)
     }
+}
 
-    Ok(nodes)
+/// Finds the line and column of offsets into the input, moving from the offset it
+/// was last asked about, so that reporting many errors doesn't go through the input
+/// from the start for each of them.
+#[derive(Clone, Debug)]
+struct Lines {
+    offset: usize,
+    line: usize,
+    column: usize,
 }
 
-fn parse_children<'input>(
-    tag: &'input str,
-    mut input: &'input str,
-) -> ParseResult<'input, Vec<Node<'input>>> {
-    let mut children = Vec::new();
-    loop {
-        if input.is_empty() {
-            return Err(ParseError::UnclosedTag(tag));
+impl Lines {
+    fn new() -> Self {
+        Lines {
+            offset: 0,
+            line: 1,
+            column: 1,
         }
+    }
 
-        let (child, rest) = Node::from_str(input)?;
-        input = rest;
-        if let Some(child) = child {
-            children.push(child);
+    fn error<'a>(
+        &mut self,
+        kind: ErrorKind<'a>,
+        input: &str,
+        offset: usize,
+    ) -> ParseError<'a> {
+        if offset >= self.offset {
+            let skipped = &input[self.offset..offset];
+            match skipped.rfind('\n') {
+                Some(i) => {
+                    self.line += skipped.matches('\n').count();
+                    self.column = skipped[i + 1..].chars().count() + 1;
+                }
+                None => self.column += skipped.chars().count(),
+            }
         } else {
-            break;
+            let skipped = &input[offset..self.offset];
+            if skipped.contains('\n') {
+                self.line -= skipped.matches('\n').count();
+                let before = &input[..offset];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                self.column = before[line_start..].chars().count() + 1;
+            } else {
+                self.column -= skipped.chars().count();
+            }
+        }
+        self.offset = offset;
+
+        ParseError {
+            kind,
+            offset,
+            line: self.line,
+            column: self.column,
         }
     }
+}
 
-    Ok((children, input))
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {} (byte {})",
+            self.kind, self.line, self.column, self.offset
+        )
+    }
 }
 
-impl<'a> Node<'a> {
-    fn from_str<'input>(input: &'input str) -> ParseResult<'input, Option<Node<'input>>> {
+impl std::error::Error for ParseError<'_> {}
+
+const X: char = '\x05';
+const Y: char = '\x06';
+
+/// An event produced by [`Reader`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    Start {
+        name: &'a str,
+        attrs: Attributes<'a>,
+    },
+    Text(&'a str),
+    /// The end of the innermost open tag, whose name is included for convenience.
+    End(&'a str),
+}
+
+/// An [`Event`] whose attributes were put in a buffer given by the caller, which
+/// can then be reused for the next tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RawEvent<'a> {
+    Start(&'a str),
+    Text(&'a str),
+    End(&'a str),
+}
+
+/// A streaming parser, which yields the tags and text as they are encountered,
+/// without building the tree.
+///
+/// After an error is returned, the iterator is exhausted.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    source: &'a str,
+    input: &'a str,
+    /// Names and offsets of the tags that are currently open.
+    open_tags: Vec<(&'a str, usize)>,
+    /// Tags opened before the start of the input, which it may close. Their ends
+    /// are reported with an empty name.
+    outer_tags: usize,
+    /// Whether to recover from errors instead of returning them, as used by
+    /// [`parse_lossy`].
+    recover: bool,
+    /// The errors that were recovered from.
+    diagnostics: Vec<ParseError<'a>>,
+    max_depth: Option<usize>,
+    reject_duplicates: bool,
+    /// The position of the last error, to find that of the next one from.
+    lines: Lines,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Reader {
+            source: input,
+            input,
+            open_tags: Vec::new(),
+            outer_tags: 0,
+            recover: false,
+            diagnostics: Vec::new(),
+            max_depth: None,
+            reject_duplicates: false,
+            lines: Lines::new(),
+        }
+    }
+
+    /// Report an error for tags nested more than `depth` levels deep. The reader
+    /// itself handles any depth, but the recursive processing of the resulting
+    /// tree, including dropping it, may overflow the stack.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Report an error for tags that have the same attribute more than once. By
+    /// default, all the values are kept, and [`Attributes::get`] returns the last
+    /// one.
+    pub fn reject_duplicate_attributes(mut self) -> Self {
+        self.reject_duplicates = true;
+        self
+    }
+
+    /// Read input that continues after `count` tags were opened, like the next chunk
+    /// read by [`Parser`].
+    pub(crate) fn with_outer_tags(mut self, count: usize) -> Self {
+        self.outer_tags = count;
+        self
+    }
+
+    /// Parse the rest of the input into a tree.
+    pub fn into_tree(mut self) -> Result<Vec<Node<'a>>, ParseError<'a>> {
+        build_tree(&mut self)
+    }
+
+    /// The byte offset of the next event within the input.
+    pub fn offset(&self) -> usize {
+        self.source.len() - self.input.len()
+    }
+
+    /// The number of tags that are currently open.
+    pub fn depth(&self) -> usize {
+        self.open_tags.len() + self.outer_tags
+    }
+
+    /// Read the next event, putting the attributes of a tag in `attrs` instead of
+    /// allocating them for each tag, as the [`Iterator`] implementation does.
+    pub(crate) fn next_raw(
+        &mut self,
+        attrs: &mut Vec<(&'a str, &'a str)>,
+    ) -> Option<Result<RawEvent<'a>, ParseError<'a>>> {
+        let result = self.next_event(attrs);
+        if result.is_err() {
+            self.input = "";
+            self.open_tags.clear();
+            self.outer_tags = 0;
+        }
+
+        result.transpose()
+    }
+
+    fn next_event(
+        &mut self,
+        attrs: &mut Vec<(&'a str, &'a str)>,
+    ) -> Result<Option<RawEvent<'a>>, ParseError<'a>> {
+        loop {
+            attrs.clear();
+            match self.try_next_event(attrs) {
+                Err(error) if self.recover => {
+                    self.diagnostics.push(error);
+                    self.skip_malformed(error.kind);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Skip past the input that caused an error, so that parsing can resume.
+    fn skip_malformed(&mut self, kind: ErrorKind<'a>) {
+        match kind {
+            // Most likely a truncated file, so there's nothing after this.
+            ErrorKind::NoClosingX => self.input = "",
+            // Drop the whole tag delimiter, which always has its closing X.
+            _ => {
+                let end = self.input[1..].find(X).unwrap() + 1;
+                self.input = &self.input[end + 1..];
+            }
+        }
+    }
+
+    fn try_next_event(
+        &mut self,
+        attrs: &mut Vec<(&'a str, &'a str)>,
+    ) -> Result<Option<RawEvent<'a>>, ParseError<'a>> {
+        let input = self.input;
+        let source = self.source;
+        let start = self.offset();
+        let depth = self.depth();
+        let lines = &mut self.lines;
+        let mut error = |kind| lines.error(kind, source, start);
         match input.find(X) {
             Some(0) => {
-                let end = input[1..].find(X).ok_or(ParseError::NoClosingX)?;
+                let end = input[1..]
+                    .find(X)
+                    .ok_or_else(|| error(ErrorKind::NoClosingX))?;
                 let (attributes, rest) = input[1..].split_at(end);
-                let rest = &rest[1..];
                 if attributes == "\x06" {
-                    Ok((None, rest))
+                    let name = match self.open_tags.pop() {
+                        Some((name, _)) => name,
+                        None if self.outer_tags > 0 => {
+                            self.outer_tags -= 1;
+                            ""
+                        }
+                        None => return Err(error(ErrorKind::UnmatchedClosingTag)),
+                    };
+                    self.input = &rest[1..];
+                    Ok(Some(RawEvent::End(name)))
                 } else {
                     let mut attributes = attributes.split(Y);
                     if attributes.next() != Some("") {
-                        return Err(ParseError::UnexpectedContentBeforeAttributes);
+                        return Err(error(ErrorKind::UnexpectedContentBeforeAttributes));
                     }
 
-                    let name = attributes.next().ok_or(ParseError::MissingName)?;
-                    let attrs = attributes
-                        .map(|attr| {
-                            let offset =
-                                attr.find('=').ok_or(ParseError::MalformedAttribute)?;
-                            Ok((&attr[0..offset], &attr[offset + 1..]))
-                        })
-                        .collect::<Result<_, _>>()?;
-
-                    let (children, rest) = parse_children(name, rest)?;
-                    Ok((
-                        Some(Node::Tag {
-                            name,
-                            attrs,
-                            children,
-                        }),
-                        rest,
-                    ))
+                    let name = attributes
+                        .next()
+                        .ok_or_else(|| error(ErrorKind::MissingName))?;
+                    if self.max_depth.is_some_and(|max| depth >= max) {
+                        return Err(error(ErrorKind::TooDeep));
+                    }
+
+                    for attr in attributes {
+                        match attr.find('=') {
+                            Some(offset) => {
+                                let key = &attr[0..offset];
+                                if self.reject_duplicates
+                                    && attrs.iter().any(|&(k, _)| k == key)
+                                {
+                                    let error = error(ErrorKind::DuplicateAttribute(key));
+                                    if !self.recover {
+                                        return Err(error);
+                                    }
+                                    self.diagnostics.push(error);
+                                }
+                                attrs.push((key, &attr[offset + 1..]))
+                            }
+                            // Keep the tag, so that its closing tag stays matched.
+                            None if self.recover => self
+                                .diagnostics
+                                .push(error(ErrorKind::MalformedAttribute)),
+                            None => return Err(error(ErrorKind::MalformedAttribute)),
+                        }
+                    }
+
+                    self.open_tags.push((name, start));
+                    self.input = &rest[1..];
+                    Ok(Some(RawEvent::Start(name)))
                 }
             }
             Some(n) => {
                 let (text, rest) = input.split_at(n);
-                Ok((Some(Node::Text(text)), rest))
+                self.input = rest;
+                Ok(Some(RawEvent::Text(text)))
             }
-            None => Ok((Some(Node::Text(input)), "")),
+            None if !input.is_empty() => {
+                self.input = "";
+                Ok(Some(RawEvent::Text(input)))
+            }
+            None => match self.open_tags.last() {
+                Some(&(name, offset)) => {
+                    let kind = ErrorKind::UnclosedTag(name);
+                    let error = self.lines.error(kind, source, offset);
+                    if !self.recover {
+                        return Err(error);
+                    }
+
+                    self.diagnostics.push(error);
+                    self.open_tags.pop();
+                    Ok(Some(RawEvent::End(name)))
+                }
+                None => Ok(None),
+            },
         }
     }
 }
 
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Event<'a>, ParseError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut attrs = Vec::new();
+        let result = self.next_raw(&mut attrs)?;
+        Some(result.map(|event| match event {
+            RawEvent::Start(name) => Event::Start {
+                name,
+                attrs: Attributes(attrs),
+            },
+            RawEvent::Text(text) => Event::Text(text),
+            RawEvent::End(name) => Event::End(name),
+        }))
+    }
+}
+
+pub fn parse<'input>(
+    input: &'input str,
+) -> Result<Vec<Node<'input>>, ParseError<'input>> {
+    build_tree(&mut Reader::new(input))
+}
+
+/// Callbacks for [`parse_with`]. All of them do nothing by default.
+pub trait Handler<'a> {
+    fn start(&mut self, name: &'a str, attrs: &Attributes<'a>) {
+        let _ = (name, attrs);
+    }
+
+    fn text(&mut self, text: &'a str) {
+        let _ = text;
+    }
+
+    /// The end of the innermost open tag, whose name is included for convenience.
+    fn end(&mut self, name: &'a str) {
+        let _ = name;
+    }
+}
+
+/// Parse YXML, calling `handler` for each tag and text in order, without building
+/// a tree. If an error is found, the callbacks for the input before it have
+/// already been made.
+pub fn parse_with<'input>(
+    input: &'input str,
+    handler: &mut impl Handler<'input>,
+) -> Result<(), ParseError<'input>> {
+    for event in Reader::new(input) {
+        match event? {
+            Event::Start { name, attrs } => handler.start(name, &attrs),
+            Event::Text(text) => handler.text(text),
+            Event::End(name) => handler.end(name),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse YXML, failing with [`ErrorKind::TooDeep`] if tags are nested more than
+/// `max_depth` levels deep.
+pub fn parse_with_max_depth(
+    input: &str,
+    max_depth: usize,
+) -> Result<Vec<Node<'_>>, ParseError<'_>> {
+    build_tree(&mut Reader::new(input).with_max_depth(max_depth))
+}
+
+/// Parse YXML, recovering from any errors instead of giving up. Malformed tag
+/// delimiters, attributes and unmatched closing tags are dropped, and tags that are
+/// still open at the end of the input get closed, as happens with a truncated dump.
+/// The errors that were recovered from are returned alongside the tree.
+pub fn parse_lossy(input: &str) -> (Vec<Node<'_>>, Vec<ParseError<'_>>) {
+    let mut reader = Reader::new(input);
+    reader.recover = true;
+    let nodes = build_tree(&mut reader).expect("errors are recovered from");
+    (nodes, reader.diagnostics)
+}
+
+fn build_tree<'input>(
+    reader: &mut Reader<'input>,
+) -> Result<Vec<Node<'input>>, ParseError<'input>> {
+    let mut nodes = Vec::new();
+    let mut parents = Vec::new();
+    for event in reader {
+        match event? {
+            Event::Start { name, attrs } => {
+                parents.push((name, attrs, std::mem::take(&mut nodes)));
+            }
+            Event::Text(text) => nodes.push(Node::Text(text)),
+            Event::End(_) => {
+                let (name, attrs, siblings) = parents.pop().unwrap();
+                let children = std::mem::replace(&mut nodes, siblings);
+                nodes.push(Node::Tag {
+                    name,
+                    attrs,
+                    children,
+                });
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Parse YXML that may contain invalid UTF-8. The markup delimiters are all ASCII,
+/// so invalid sequences can only occur within text and attribute values, where they
+/// get replaced with U+FFFD.
+///
+/// If the input is valid UTF-8, the tree borrows from it directly. Otherwise, a
+/// cleaned up copy is stored in `buf`, and the tree borrows from that. Error
+/// positions then refer to the copy as well.
+pub fn parse_bytes<'input>(
+    input: &'input [u8],
+    buf: &'input mut String,
+) -> Result<Vec<Node<'input>>, ParseError<'input>> {
+    match std::str::from_utf8(input) {
+        Ok(input) => parse(input),
+        Err(_) => {
+            *buf = String::from_utf8_lossy(input).into_owned();
+            parse(buf)
+        }
+    }
+}
+
+/// An error produced when serializing a tree that can't be represented in YXML.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WriteError<'a> {
+    /// Text, a tag name or an attribute contains one of the control characters
+    /// YXML uses as delimiters.
+    ReservedCharacter(&'a str),
+    /// An attribute name contains `=`.
+    MalformedAttribute(&'a str),
+    MissingName,
+}
+
+impl fmt::Display for WriteError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::ReservedCharacter(s) => {
+                write!(f, "{:?} contains a reserved control character", s)
+            }
+            WriteError::MalformedAttribute(name) => {
+                write!(f, "attribute name {:?} contains '='", name)
+            }
+            WriteError::MissingName => write!(f, "tag with an empty name"),
+        }
+    }
+}
+
+impl std::error::Error for WriteError<'_> {}
+
+fn check_reserved(s: &str) -> Result<(), WriteError<'_>> {
+    if s.contains([X, Y]) {
+        Err(WriteError::ReservedCharacter(s))
+    } else {
+        Ok(())
+    }
+}
+
+fn write_nodes<'a>(out: &mut String, nodes: &[Node<'a>]) -> Result<(), WriteError<'a>> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                check_reserved(text)?;
+                out.push_str(text);
+            }
+            Node::Tag {
+                name,
+                attrs,
+                children,
+            } => {
+                if name.is_empty() {
+                    return Err(WriteError::MissingName);
+                }
+
+                check_reserved(name)?;
+                out.push(X);
+                out.push(Y);
+                out.push_str(name);
+                for (key, value) in attrs.iter() {
+                    check_reserved(key)?;
+                    check_reserved(value)?;
+                    if key.contains('=') {
+                        return Err(WriteError::MalformedAttribute(key));
+                    }
+
+                    out.push(Y);
+                    out.push_str(key);
+                    out.push('=');
+                    out.push_str(value);
+                }
+                out.push(X);
+                write_nodes(out, children)?;
+                out.push(X);
+                out.push(Y);
+                out.push(X);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Serialize a tree back into YXML.
+pub fn to_string<'a>(nodes: &[Node<'a>]) -> Result<String, WriteError<'a>> {
+    let mut out = String::new();
+    write_nodes(&mut out, nodes)?;
+    Ok(out)
+}
+
+/// Serialize a tree into YXML, writing the result to `w`. A tree that can't be
+/// represented is reported as an error of kind [`io::ErrorKind::InvalidInput`].
+pub fn write(mut w: impl io::Write, nodes: &[Node<'_>]) -> io::Result<()> {
+    let s = to_string(nodes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    w.write_all(s.as_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // https://stackoverflow.com/a/27582993
     macro_rules! map(
         { $($key:expr => $value:expr),* } => {
-            {
-                #[allow(unused_mut)]
-                let mut m = ::std::collections::HashMap::new();
-                $(
-                    m.insert($key, $value);
-                )*
-                m
-            }
+            vec![$(($key, $value)),*].into()
          };
     );
 
@@ -128,11 +923,7 @@ mod tests {
     fn it_works() {
         assert_eq!(
             parse("\x05\x06tag\x05hi\x05\x06\x05"),
-            Ok(vec![Node::Tag {
-                name: "tag",
-                attrs: map! {},
-                children: vec![Node::Text("hi")]
-            }])
+            Ok(vec![Node::tag("tag").child("hi").build()])
         );
     }
 
@@ -140,56 +931,327 @@ mod tests {
     fn equal_sign_in_attribute() {
         assert_eq!(
             parse("\x05\x06tag\x06attr=2+2=4\x05hi\x05\x06\x05"),
-            Ok(vec![Node::Tag {
-                name: "tag",
-                attrs: map! { "attr" => "2+2=4" },
-                children: vec![Node::Text("hi")]
-            }])
+            Ok(vec![Node::tag("tag")
+                .attr("attr", "2+2=4")
+                .child("hi")
+                .build()])
         );
     }
 
+    #[test]
+    fn duplicate_attributes() {
+        let input = "x\x05\x06tag\x06a=1\x06b=2\x06a=3\x05\x05\x06\x05";
+        let nodes = parse(input).unwrap();
+        let attrs = match &nodes[1] {
+            Node::Tag { attrs, .. } => attrs,
+            Node::Text(_) => unreachable!(),
+        };
+        assert_eq!(attrs.get("a"), Some("3"));
+        assert_eq!(attrs.get_all("a").collect::<Vec<_>>(), ["1", "3"]);
+
+        let err = Reader::new(input)
+            .reject_duplicate_attributes()
+            .into_tree()
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DuplicateAttribute("a"));
+        assert_eq!(err.offset, 1);
+    }
+
     #[test]
     fn unclosed_tag() {
-        assert_eq!(
-            parse("\x05\x06tag\x05hi"),
-            Err(ParseError::UnclosedTag("tag"))
-        );
+        let err = parse("hi\n\x05\x06tag\x05hi").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnclosedTag("tag"));
+        assert_eq!((err.offset, err.line, err.column), (3, 2, 1));
     }
 
     #[test]
     fn no_closing_x() {
-        assert_eq!(parse("\x05\x06tag"), Err(ParseError::NoClosingX));
+        let err = parse("\x05\x06tag").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NoClosingX);
+        assert_eq!(err.offset, 0);
     }
 
     #[test]
     fn unexpected_content_before_attributes() {
         assert_eq!(
-            parse("\x05xxx\x06tag\x05hi\x05\x06\x05"),
-            Err(ParseError::UnexpectedContentBeforeAttributes)
+            parse("\x05xxx\x06tag\x05hi\x05\x06\x05").map_err(|e| e.kind),
+            Err(ErrorKind::UnexpectedContentBeforeAttributes)
         );
     }
 
     #[test]
     fn missing_name() {
         assert_eq!(
-            parse("\x05\x05hi\x05\x06\x05"),
-            Err(ParseError::MissingName)
+            parse("\x05\x05hi\x05\x06\x05").map_err(|e| e.kind),
+            Err(ErrorKind::MissingName)
         );
     }
 
     #[test]
     fn malformed_attribute() {
         assert_eq!(
-            parse("\x05\x06tag\x06bad_attr\x05hi\x05\x06\x05"),
-            Err(ParseError::MalformedAttribute)
+            parse("\x05\x06tag\x06bad_attr\x05hi\x05\x06\x05").map_err(|e| e.kind),
+            Err(ErrorKind::MalformedAttribute)
         );
     }
 
     #[test]
     fn unmatched_closing_tag() {
+        let err = parse("\x05\x06tag\x05hi\x05\x06\x05\x05\x06\x05").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnmatchedClosingTag);
+        assert_eq!((err.offset, err.line, err.column), (11, 1, 12));
+    }
+
+    #[test]
+    fn reader_events() {
+        let events: Result<Vec<_>, _> =
+            Reader::new("\x05\x06tag\x06a=b\x05hi\x05\x06\x05bye").collect();
+        assert_eq!(
+            events,
+            Ok(vec![
+                Event::Start {
+                    name: "tag",
+                    attrs: map! { "a" => "b" },
+                },
+                Event::Text("hi"),
+                Event::End("tag"),
+                Event::Text("bye"),
+            ])
+        );
+    }
+
+    #[test]
+    fn reader_stops_after_error() {
+        let mut reader = Reader::new("\x05\x06tag\x05\x05\x06\x05\x05\x06\x05hi");
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(
+            reader.next().unwrap().map_err(|e| e.kind),
+            Err(ErrorKind::UnmatchedClosingTag)
+        );
+        assert_eq!(reader.next(), None);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 100_000;
+        let input = "\x05\x06a\x05".repeat(depth) + &"\x05\x06\x05".repeat(depth);
+        let reader = Reader::new(&input);
+        assert_eq!(reader.map(Result::unwrap).count(), 2 * depth);
+
+        let err = parse_with_max_depth(&input, 100).unwrap_err();
+        assert_eq!((err.kind, err.offset), (ErrorKind::TooDeep, 400));
+        let input = "\x05\x06a\x05".repeat(100) + &"\x05\x06\x05".repeat(100);
+        assert!(parse_with_max_depth(&input, 100).is_ok());
+    }
+
+    #[test]
+    fn lossy_recovery() {
+        let input = "\x05\x06a\x06bad\x06k=v\x05x\x05\x06\x05\x05\x06\x05\
+            \x05\x06b\x05y\x05\x06c";
+        let (nodes, errors) = parse_lossy(input);
+        assert_eq!(
+            nodes,
+            vec![
+                Node::tag("a").attr("k", "v").child("x").build(),
+                Node::tag("b").child("y").build(),
+            ]
+        );
+        let errors: Vec<_> = errors.iter().map(|e| (e.kind, e.offset)).collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorKind::MalformedAttribute, 0),
+                (ErrorKind::UnmatchedClosingTag, 16),
+                (ErrorKind::NoClosingX, 24),
+                (ErrorKind::UnclosedTag("b"), 19),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let err = parse("‹x›\n\x05\x06tag\x06a\x05\x05\x06\x05").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "attribute without '=' at line 2, column 1 (byte 8)"
+        );
+    }
+
+    #[test]
+    fn lossy_error_positions() {
+        let input =
+            "\x05\x06a\x05‹\x05\x06b\x06x\x05\n\x05\x06\x05\x05\x06\x05\x05\x06\x05\n\
+             \x05\x06c\x05ü\x05\x06e\x05\n\x05\x06d\x05";
+        let (_, errors) = parse_lossy(input);
+        let positions: Vec<_> =
+            errors.iter().map(|e| (e.kind, e.line, e.column)).collect();
+        assert_eq!(
+            positions,
+            [
+                (ErrorKind::MalformedAttribute, 1, 6),
+                (ErrorKind::UnmatchedClosingTag, 2, 7),
+                (ErrorKind::UnclosedTag("d"), 4, 1),
+                (ErrorKind::UnclosedTag("e"), 3, 6),
+                (ErrorKind::UnclosedTag("c"), 3, 1),
+            ]
+        );
+        // The same as when each is found from the start of the input.
+        for error in &errors {
+            assert_eq!(*error, ParseError::new(error.kind, input, error.offset));
+        }
+    }
+
+    #[test]
+    fn attribute_order() {
+        let nodes = parse("\x05\x06tag\x06b=1\x06a=2\x06b=3\x05\x05\x06\x05").unwrap();
+        match &nodes[0] {
+            Node::Tag { attrs, .. } => {
+                assert_eq!(attrs.as_slice(), [("b", "1"), ("a", "2"), ("b", "3")]);
+                assert_eq!(attrs.get("b"), Some("3"));
+                assert_eq!(&attrs["a"], "2");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut buf = String::new();
+        assert_eq!(
+            parse_bytes(b"\x05\x06tag\x05a\xffb\x05\x06\x05", &mut buf),
+            Ok(vec![Node::tag("tag").child("a\u{fffd}b").build()])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let input =
+            "\x05\x06tag\x06z=1\x06attr=2+2=4\x05hi\x05\x06inner\x05\x05\x06\x05\x05\x06\x05!";
+        let nodes = parse(input).unwrap();
+        assert_eq!(to_string(&nodes), Ok(input.to_owned()));
+    }
+
+    #[test]
+    fn write_rejects_reserved_characters() {
+        assert_eq!(
+            to_string(&[Node::Text("a\x05b")]),
+            Err(WriteError::ReservedCharacter("a\x05b"))
+        );
+        assert_eq!(
+            to_string(&[Node::tag("tag").attr("a=b", "c").build()]),
+            Err(WriteError::MalformedAttribute("a=b"))
+        );
+    }
+
+    #[test]
+    fn owned_outlives_input() {
+        let owned: Vec<OwnedNode> = {
+            let input = String::from("\x05\x06tag\x06a=b\x05hi\x05\x06\x05");
+            let nodes = parse(&input).unwrap();
+            nodes.into_iter().map(Node::into_owned).collect()
+        };
+
+        assert_eq!(
+            owned,
+            [OwnedNode::Tag {
+                name: "tag".to_owned(),
+                attrs: vec![("a".to_owned(), "b".to_owned())],
+                children: vec![OwnedNode::Text("hi".to_owned())],
+            }]
+        );
+        assert_eq!(
+            owned[0].borrow(),
+            Node::tag("tag").attr("a", "b").child("hi").build()
+        );
+    }
+
+    #[test]
+    fn traversal() {
+        let nodes = parse("\x05\x06a\x05\x05\x06b\x05x\x05\x06\x05\x05\x06c\x05\x05\x06\x05\x05\x06b\x05\x05\x06\x05\x05\x06\x05")
+            .unwrap();
+        let root = &nodes[0];
+        let walk: Vec<_> = root
+            .walk()
+            .map(|(depth, node)| match node {
+                Node::Tag { name, .. } => (depth, *name),
+                Node::Text(text) => (depth, *text),
+            })
+            .collect();
+        assert_eq!(walk, [(0, "a"), (1, "b"), (2, "x"), (1, "c"), (1, "b")]);
+        assert_eq!(root.descendants().count(), 4);
+        assert_eq!(root.children_named("b").count(), 2);
+    }
+
+    #[test]
+    fn callbacks() {
+        #[derive(Default)]
+        struct Stats {
+            tags: usize,
+            text_len: usize,
+            max_depth: usize,
+            depth: usize,
+        }
+
+        impl<'a> Handler<'a> for Stats {
+            fn start(&mut self, _: &'a str, _: &Attributes<'a>) {
+                self.tags += 1;
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+
+            fn text(&mut self, text: &'a str) {
+                self.text_len += text.len();
+            }
+
+            fn end(&mut self, _: &'a str) {
+                self.depth -= 1;
+            }
+        }
+
+        let mut stats = Stats::default();
+        let input = "ab\x05\x06t\x05\x05\x06u\x05c\x05\x06\x05\x05\x06\x05";
+        parse_with(input, &mut stats).unwrap();
+        assert_eq!((stats.tags, stats.text_len, stats.max_depth), (2, 3, 2));
+        assert!(parse_with("\x05\x06t\x05", &mut stats).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let long = "x".repeat(100);
+        let input = format!(
+            "\x05\x06a\x06k=v\x05t\x05\x06b\x05{}\x05\x06\x05\x05\x06\x05",
+            long
+        );
+        let nodes = parse(&input).unwrap();
         assert_eq!(
-            parse("\x05\x06tag\x05hi\x05\x06\x05\x05\x06\x05"),
-            Err(ParseError::UnmatchedClosingTag)
+            dump_tree(&nodes),
+            format!("a k=\"v\"\n  \"t\"\n  b\n    \"{}\"...\n", &long[..60])
         );
     }
+
+    #[test]
+    fn source_text() {
+        let nodes = parse(
+            "\x05\x06xml_elem\x06xml_name=typing\x05\
+             \x05\x06xml_body\x05'a\x05\x06\x05x \\<and> y\x05\x06\x05",
+        )
+        .unwrap();
+        assert_eq!(nodes[0].text_content(), "'ax \\<and> y");
+        assert_eq!(nodes[0].to_source_text(), "x \\<and> y");
+    }
+
+    #[test]
+    fn accessors() {
+        let nodes = parse("\x05\x06a\x06k=v\x05x\x05\x06b\x05y\x05\x06\x05z\x05\x06\x05")
+            .unwrap();
+        let root = &nodes[0];
+        assert_eq!(root.name(), Some("a"));
+        assert_eq!(root.attr("k"), Some("v"));
+        assert_eq!(root.attr("missing"), None);
+        assert_eq!(root.text_content(), "xyz");
+        assert_eq!(root.to_source_text(), "xyz");
+        assert_eq!(root.children()[0].name(), None);
+        assert_eq!(root.children()[0].attr("k"), None);
+    }
 }
@@ -0,0 +1,163 @@
+//! Editing trees in place, as when post-processing a dump before publishing it.
+
+use crate::{Attributes, Node};
+
+impl<'a> Attributes<'a> {
+    /// Set the value of the attribute `key`. If it occurs multiple times, only the
+    /// last occurrence is changed, which is the one [`Attributes::get`] returns.
+    pub fn set(&mut self, key: &'a str, value: &'a str) {
+        match self.0.iter_mut().rev().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    /// Remove all occurrences of the attribute `key`, returning the value of the
+    /// last one.
+    pub fn remove(&mut self, key: &str) -> Option<&'a str> {
+        let value = self.get(key);
+        self.0.retain(|(k, _)| *k != key);
+        value
+    }
+}
+
+impl<'a> Node<'a> {
+    fn tag_parts(&mut self) -> (&mut &'a str, &mut Attributes<'a>, &mut Vec<Node<'a>>) {
+        match self {
+            Node::Text(_) => panic!("text nodes can't be edited as tags"),
+            Node::Tag {
+                name,
+                attrs,
+                children,
+            } => (name, attrs, children),
+        }
+    }
+
+    /// The children of a tag, for arbitrary edits. Text nodes have no children.
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Node<'a>>> {
+        match self {
+            Node::Text(_) => None,
+            Node::Tag { children, .. } => Some(children),
+        }
+    }
+
+    /// Insert a child of a tag at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a text node, or if `index` is out of bounds.
+    pub fn insert_child(&mut self, index: usize, child: impl Into<Node<'a>>) {
+        self.tag_parts().2.insert(index, child.into());
+    }
+
+    /// Append a child to a tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a text node.
+    pub fn push_child(&mut self, child: impl Into<Node<'a>>) {
+        self.tag_parts().2.push(child.into());
+    }
+
+    /// Remove and return the child of a tag at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a text node, or if `index` is out of bounds.
+    pub fn remove_child(&mut self, index: usize) -> Node<'a> {
+        self.tag_parts().2.remove(index)
+    }
+
+    /// Change the name of a tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a text node.
+    pub fn rename(&mut self, name: &'a str) {
+        *self.tag_parts().0 = name;
+    }
+
+    /// Set an attribute of a tag, replacing its previous value if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a text node.
+    pub fn set_attr(&mut self, key: &'a str, value: &'a str) {
+        self.tag_parts().1.set(key, value);
+    }
+
+    /// Remove an attribute of a tag, returning its previous value. Text nodes have
+    /// no attributes.
+    pub fn remove_attr(&mut self, key: &str) -> Option<&'a str> {
+        match self {
+            Node::Text(_) => None,
+            Node::Tag { attrs, .. } => attrs.remove(key),
+        }
+    }
+
+    /// Remove every node below this one for which `keep` returns false, along with
+    /// its subtree. The children of a removed node are not visited.
+    pub fn retain_recursive<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Node<'a>) -> bool,
+    {
+        if let Some(children) = self.children_mut() {
+            retain_recursive(children, &mut keep);
+        }
+    }
+}
+
+/// Like [`Node::retain_recursive`], for a list of nodes, such as the top level of a
+/// document. The nodes in the list are themselves subject to `keep`.
+pub fn retain_recursive<'a, F>(nodes: &mut Vec<Node<'a>>, keep: &mut F)
+where
+    F: FnMut(&Node<'a>) -> bool,
+{
+    nodes.retain(|node| keep(node));
+    for node in nodes {
+        if let Some(children) = node.children_mut() {
+            retain_recursive(children, keep);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, to_string};
+
+    #[test]
+    fn edits() {
+        let mut node = Node::tag("a").attr("k", "v").child("x").build();
+        node.rename("b");
+        node.set_attr("k", "w");
+        node.set_attr("l", "u");
+        node.insert_child(0, Node::tag("c"));
+        node.push_child("y");
+        assert_eq!(node.remove_child(1), Node::Text("x"));
+        assert_eq!(node.remove_attr("l"), Some("u"));
+        assert_eq!(
+            node,
+            Node::tag("b")
+                .attr("k", "w")
+                .child(Node::tag("c"))
+                .child("y")
+                .build()
+        );
+    }
+
+    #[test]
+    fn retain() {
+        let mut nodes = parse(
+            "\x05\x06a\x05\x05\x06private\x05x\x05\x06\x05\
+             \x05\x06b\x05\x05\x06private\x05\x05\x06\x05y\x05\x06\x05\x05\x06\x05\
+             \x05\x06private\x05\x05\x06\x05",
+        )
+        .unwrap();
+        retain_recursive(&mut nodes, &mut |node| node.name() != Some("private"));
+        assert_eq!(
+            to_string(&nodes).unwrap(),
+            "\x05\x06a\x05\x05\x06b\x05y\x05\x06\x05\x05\x06\x05"
+        );
+    }
+}
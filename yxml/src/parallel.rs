@@ -0,0 +1,95 @@
+//! Parsing many files at once, as when converting a whole session.
+
+use crate::{parse_bytes, OwnedNode};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+fn parse_file(path: &Path) -> io::Result<Vec<OwnedNode>> {
+    let bytes = std::fs::read(path)?;
+    let mut buf = String::new();
+    let nodes = parse_bytes(&bytes, &mut buf).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })?;
+    Ok(nodes.into_iter().map(OwnedNode::from).collect())
+}
+
+/// Read and parse each of `paths`, spreading the work over one thread per CPU. The
+/// results are in the same order as the paths. Parse errors are reported as
+/// [`io::ErrorKind::InvalidData`], and invalid UTF-8 is replaced as in
+/// [`parse_bytes`].
+pub fn parse_files<P>(paths: &[P]) -> Vec<io::Result<Vec<OwnedNode>>>
+where
+    P: AsRef<Path> + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(paths.len());
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<Vec<OwnedNode>>>>> =
+        Mutex::new((0..paths.len()).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(i) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let result = parse_file(path.as_ref());
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every file is parsed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_many() {
+        let dir =
+            std::env::temp_dir().join(format!("yxml-parallel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = vec![];
+        for i in 0..20 {
+            let path = dir.join(format!("{}.yxml", i));
+            std::fs::write(&path, format!("\x05\x06n\x06i={}\x05\x05\x06\x05", i))
+                .unwrap();
+            paths.push(path);
+        }
+        std::fs::write(dir.join("bad.yxml"), "\x05\x06a\x05").unwrap();
+        paths.push(dir.join("bad.yxml"));
+        paths.push(dir.join("missing.yxml"));
+
+        let results = parse_files(&paths);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results.len(), 22);
+        for (i, result) in results[..20].iter().enumerate() {
+            let nodes = result.as_ref().unwrap();
+            assert_eq!(nodes[0].borrow().attr("i"), Some(&*i.to_string()));
+        }
+        assert_eq!(
+            results[20].as_ref().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            results[21].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! CSS-like selectors for picking subtrees out of a YXML tree.
+//!
+//! The supported syntax is a subset of CSS: tag names (or `*`), attribute
+//! conditions in the form `[name]` or `[name=value]`, and the descendant (` `) and
+//! child (`>`) combinators. Values may be quoted with `"`.
+
+use crate::Node;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorError {
+    /// Byte offset within the selector where the problem was found.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+/// A parsed selector.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    /// How this step relates to the previous one. Ignored for the first step.
+    combinator: Combinator,
+    name: Option<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Step {
+    fn matches(&self, node: &Node<'_>) -> bool {
+        match node {
+            Node::Text(_) => false,
+            Node::Tag { name, attrs, .. } => {
+                self.name.as_ref().is_none_or(|n| n == name)
+                    && self.attrs.iter().all(|(key, value)| {
+                        match (attrs.get(key), value) {
+                            (Some(actual), Some(value)) => actual == value,
+                            (Some(_), None) => true,
+                            (None, _) => false,
+                        }
+                    })
+            }
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '>' | '[' | ']' | '=' | '"')
+}
+
+struct Parser<'s> {
+    input: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error<T>(&self, message: &'static str) -> Result<T, SelectorError> {
+        Err(SelectorError {
+            offset: self.pos,
+            message,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let rest = &self.input[self.pos..];
+        let trimmed = rest.trim_start();
+        self.pos += rest.len() - trimmed.len();
+        rest.len() != trimmed.len()
+    }
+
+    fn name(&mut self) -> &'s str {
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn value(&mut self) -> Result<String, SelectorError> {
+        if self.peek() != Some('"') {
+            return Ok(self.name().to_owned());
+        }
+
+        let rest = &self.input[self.pos + 1..];
+        match rest.find('"') {
+            Some(len) => {
+                self.pos += len + 2;
+                Ok(rest[..len].to_owned())
+            }
+            None => self.error("unterminated quoted value"),
+        }
+    }
+
+    fn step(&mut self, combinator: Combinator) -> Result<Step, SelectorError> {
+        let name = match self.name() {
+            "" if self.peek() == Some('[') => None,
+            "" => return self.error("expected a tag name"),
+            "*" => None,
+            name => Some(name.to_owned()),
+        };
+
+        let mut attrs = vec![];
+        while self.peek() == Some('[') {
+            self.pos += 1;
+            let key = self.name();
+            if key.is_empty() {
+                return self.error("expected an attribute name");
+            }
+
+            let value = if self.peek() == Some('=') {
+                self.pos += 1;
+                Some(self.value()?)
+            } else {
+                None
+            };
+
+            if self.peek() != Some(']') {
+                return self.error("expected ']'");
+            }
+
+            self.pos += 1;
+            attrs.push((key.to_owned(), value));
+        }
+
+        Ok(Step {
+            combinator,
+            name,
+            attrs,
+        })
+    }
+}
+
+impl Selector {
+    pub fn new(selector: &str) -> Result<Selector, SelectorError> {
+        let mut parser = Parser {
+            input: selector,
+            pos: 0,
+        };
+
+        parser.skip_whitespace();
+        let mut steps = vec![parser.step(Combinator::Descendant)?];
+        loop {
+            let had_whitespace = parser.skip_whitespace();
+            let combinator = match parser.peek() {
+                None => break,
+                Some('>') => {
+                    parser.pos += 1;
+                    parser.skip_whitespace();
+                    Combinator::Child
+                }
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(_) => return parser.error("unexpected character"),
+            };
+
+            steps.push(parser.step(combinator)?);
+        }
+
+        Ok(Selector { steps })
+    }
+
+    /// Whether the last node of `path` matches, given the nodes before it as its
+    /// ancestors.
+    fn matches_path(steps: &[Step], path: &[&Node<'_>]) -> bool {
+        let (step, rest) = match steps.split_last() {
+            Some(split) => split,
+            None => return true,
+        };
+
+        let (node, ancestors) = match path.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+
+        if !step.matches(node) {
+            return false;
+        }
+
+        if rest.is_empty() {
+            return true;
+        }
+
+        match step.combinator {
+            Combinator::Child => Self::matches_path(rest, ancestors),
+            Combinator::Descendant => (1..=ancestors.len())
+                .rev()
+                .any(|len| Self::matches_path(rest, &ancestors[..len])),
+        }
+    }
+
+    fn collect<'n, 'a>(
+        &self,
+        nodes: &'n [Node<'a>],
+        path: &mut Vec<&'n Node<'a>>,
+        out: &mut Vec<&'n Node<'a>>,
+    ) {
+        for node in nodes {
+            path.push(node);
+            if Self::matches_path(&self.steps, path) {
+                out.push(node);
+            }
+
+            self.collect(node.children(), path, out);
+            path.pop();
+        }
+    }
+
+    /// Find the nodes in `nodes` and their descendants which match the selector,
+    /// in document order.
+    pub fn select_in<'n, 'a>(&self, nodes: &'n [Node<'a>]) -> Vec<&'n Node<'a>> {
+        let mut out = vec![];
+        self.collect(nodes, &mut vec![], &mut out);
+        out
+    }
+}
+
+impl<'a> Node<'a> {
+    /// Find the descendants of this node which match `selector`, in document order.
+    /// This node itself is never included, but it is taken into account when
+    /// matching ancestors.
+    pub fn select(&self, selector: &str) -> Result<Vec<&Node<'a>>, SelectorError> {
+        let selector = Selector::new(selector)?;
+        let mut out = vec![];
+        selector.collect(self.children(), &mut vec![self], &mut out);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const INPUT: &str = "\x05\x06root\x05\
+        \x05\x06xml_elem\x06xml_name=typing\x05\
+        \x05\x06xml_body\x05'a\x05\x06\x05x\x05\x06\x05\
+        \x05\x06xml_elem\x06xml_name=ML_typing\x05\
+        \x05\x06xml_body\x05int\x05\x06\x05\x05\x06\x05\
+        \x05\x06xml_elem\x06xml_name=typing\x05\
+        \x05\x06xml_body\x05nat\x05\x06\x05y\x05\x06\x05\
+        \x05\x06\x05";
+
+    fn texts<'a>(nodes: &[&Node<'a>]) -> Vec<&'a str> {
+        nodes
+            .iter()
+            .map(|node| match node.children() {
+                [Node::Text(text)] => *text,
+                _ => panic!("unexpected node {:?}", node),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn child_and_attribute() {
+        let nodes = parse(INPUT).unwrap();
+        let found = nodes[0]
+            .select("xml_elem[xml_name=typing] > xml_body")
+            .unwrap();
+        assert_eq!(texts(&found), ["'a", "nat"]);
+    }
+
+    #[test]
+    fn descendant() {
+        let nodes = parse(INPUT).unwrap();
+        let found = nodes[0].select(r#"root [xml_name="ML_typing"] *"#).unwrap();
+        assert_eq!(texts(&found), ["int"]);
+        let found = nodes[0].select("root > xml_body").unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Selector::new("a[b").unwrap_err(),
+            SelectorError {
+                offset: 3,
+                message: "expected ']'",
+            }
+        );
+        assert!(Selector::new("a >").is_err());
+    }
+}
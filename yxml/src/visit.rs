@@ -0,0 +1,76 @@
+//! A visitor over trees, so that analyses don't each have to spell out the
+//! recursion.
+
+use crate::{Attributes, Node};
+
+/// Callbacks for the nodes of a tree, in document order. By default, tags recurse
+/// into their children and text is ignored. An implementation that overrides
+/// [`Visit::visit_tag`] decides itself whether and when to recurse, by calling
+/// [`Visit::visit_nodes`] on the children.
+pub trait Visit<'a> {
+    fn visit_tag(
+        &mut self,
+        name: &'a str,
+        attrs: &Attributes<'a>,
+        children: &[Node<'a>],
+    ) {
+        let _ = (name, attrs);
+        self.visit_nodes(children);
+    }
+
+    fn visit_text(&mut self, text: &'a str) {
+        let _ = text;
+    }
+
+    fn visit_node(&mut self, node: &Node<'a>) {
+        match node {
+            Node::Text(text) => self.visit_text(text),
+            Node::Tag {
+                name,
+                attrs,
+                children,
+            } => self.visit_tag(name, attrs, children),
+        }
+    }
+
+    fn visit_nodes(&mut self, nodes: &[Node<'a>]) {
+        for node in nodes {
+            self.visit_node(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    /// Collects the text outside of `skip` tags.
+    struct Text<'a>(Vec<&'a str>);
+
+    impl<'a> Visit<'a> for Text<'a> {
+        fn visit_tag(
+            &mut self,
+            name: &'a str,
+            _: &Attributes<'a>,
+            children: &[Node<'a>],
+        ) {
+            if name != "skip" {
+                self.visit_nodes(children);
+            }
+        }
+
+        fn visit_text(&mut self, text: &'a str) {
+            self.0.push(text);
+        }
+    }
+
+    #[test]
+    fn skip_subtree() {
+        let nodes =
+            parse("a\x05\x06b\x05c\x05\x06skip\x05d\x05\x06\x05\x05\x06\x05e").unwrap();
+        let mut visitor = Text(vec![]);
+        visitor.visit_nodes(&nodes);
+        assert_eq!(visitor.0, ["a", "c", "e"]);
+    }
+}
@@ -0,0 +1,360 @@
+//! Conversion between YXML trees and conventional XML, for use with standard XML
+//! tooling.
+//!
+//! The XML side is treated as a fragment: any number of top-level nodes is allowed,
+//! and all whitespace is kept as text, since it is significant in Isabelle's markup.
+//! Comments, processing instructions and the XML declaration are skipped, and
+//! `CDATA` sections are read as text. Document type declarations and entities other
+//! than the predefined and numeric ones are not supported.
+
+use crate::{Node, OwnedNode};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XmlError {
+    /// Byte offset within the input where the problem was found.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+fn escape(out: &mut String, s: &str, in_attribute: bool) {
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' if in_attribute => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_xml(out: &mut String, node: &Node<'_>) {
+    match node {
+        Node::Text(text) => escape(out, text, false),
+        Node::Tag {
+            name,
+            attrs,
+            children,
+        } => {
+            out.push('<');
+            out.push_str(name);
+            for (key, value) in attrs.iter() {
+                out.push(' ');
+                out.push_str(key);
+                out.push_str("=\"");
+                escape(out, value, true);
+                out.push('"');
+            }
+
+            if children.is_empty() {
+                out.push_str("/>");
+            } else {
+                out.push('>');
+                for child in children {
+                    write_xml(out, child);
+                }
+                out.push_str("</");
+                out.push_str(name);
+                out.push('>');
+            }
+        }
+    }
+}
+
+impl Node<'_> {
+    /// Serialize the tree as XML. Names are written as-is, as the ones Isabelle
+    /// uses are all valid in XML.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        write_xml(&mut out, self);
+        out
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '<' | '>' | '/' | '=' | '"' | '\'' | '&')
+}
+
+struct Parser<'s> {
+    input: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error<T>(&self, message: &'static str) -> Result<T, XmlError> {
+        Err(XmlError {
+            offset: self.pos,
+            message,
+        })
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.input[self.pos..]
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        let found = self.rest().starts_with(prefix);
+        if found {
+            self.pos += prefix.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip past the next occurrence of `end`.
+    fn skip_past(
+        &mut self,
+        end: &str,
+        message: &'static str,
+    ) -> Result<&'s str, XmlError> {
+        match self.rest().find(end) {
+            Some(len) => {
+                let skipped = &self.rest()[..len];
+                self.pos += len + end.len();
+                Ok(skipped)
+            }
+            None => self.error(message),
+        }
+    }
+
+    fn name(&mut self) -> Result<&'s str, XmlError> {
+        let rest = self.rest();
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        if len == 0 {
+            return self.error("expected a name");
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Decode the character data in `raw`, which starts at `start` in the input.
+    fn unescape(&self, raw: &str, start: usize) -> Result<String, XmlError> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(amp) = rest.find('&') {
+            out.push_str(&rest[..amp]);
+            let error = XmlError {
+                offset: start + (raw.len() - rest.len()) + amp,
+                message: "malformed entity reference",
+            };
+            let semi = rest[amp..].find(';').ok_or_else(|| error.clone())?;
+            let entity = &rest[amp + 1..amp + semi];
+            let c = match entity {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = if let Some(hex) = entity.strip_prefix("#x") {
+                        u32::from_str_radix(hex, 16).ok()
+                    } else if let Some(dec) = entity.strip_prefix('#') {
+                        dec.parse().ok()
+                    } else {
+                        None
+                    };
+                    code.and_then(char::from_u32).ok_or(error)?
+                }
+            };
+
+            out.push(c);
+            rest = &rest[amp + semi + 1..];
+        }
+
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn attribute_value(&mut self) -> Result<String, XmlError> {
+        let quote = match self.rest().chars().next() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return self.error("expected a quoted attribute value"),
+        };
+
+        self.pos += 1;
+        let start = self.pos;
+        let raw = self.skip_past(&quote.to_string(), "unterminated attribute value")?;
+        self.unescape(raw, start)
+    }
+
+    /// Parse a start tag, after the `<`. Returns whether the tag is self-closing.
+    fn start_tag(&mut self) -> Result<(OwnedNode, bool), XmlError> {
+        let name = self.name()?.to_owned();
+        let mut attrs = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.eat("/>") {
+                break Ok((tag(name, attrs), true));
+            } else if self.eat(">") {
+                break Ok((tag(name, attrs), false));
+            }
+
+            let key = self.name()?.to_owned();
+            self.skip_whitespace();
+            if !self.eat("=") {
+                return self.error("expected '='");
+            }
+
+            self.skip_whitespace();
+            attrs.push((key, self.attribute_value()?));
+        }
+    }
+}
+
+fn tag(name: String, attrs: Vec<(String, String)>) -> OwnedNode {
+    OwnedNode::Tag {
+        name,
+        attrs,
+        children: vec![],
+    }
+}
+
+fn children(node: &mut OwnedNode) -> &mut Vec<OwnedNode> {
+    match node {
+        OwnedNode::Tag { children, .. } => children,
+        OwnedNode::Text(_) => unreachable!("only tags are kept on the stack"),
+    }
+}
+
+/// Parse a fragment of XML into a tree.
+pub fn from_xml(input: &str) -> Result<Vec<OwnedNode>, XmlError> {
+    let mut parser = Parser { input, pos: 0 };
+    let mut top = vec![];
+    // The currently open tags, along with the offset of their start tags.
+    let mut stack: Vec<(OwnedNode, usize)> = vec![];
+
+    macro_rules! push {
+        ($node:expr) => {
+            match stack.last_mut() {
+                Some((parent, _)) => children(parent).push($node),
+                None => top.push($node),
+            }
+        };
+    }
+
+    while !parser.rest().is_empty() {
+        let start = parser.pos;
+        if parser.eat("<!--") {
+            parser.skip_past("-->", "unterminated comment")?;
+        } else if parser.eat("<?") {
+            parser.skip_past("?>", "unterminated processing instruction")?;
+        } else if parser.eat("<![CDATA[") {
+            let text = parser.skip_past("]]>", "unterminated CDATA section")?;
+            push!(OwnedNode::Text(text.to_owned()));
+        } else if parser.eat("<!") {
+            return parser.error("document type declarations are not supported");
+        } else if parser.eat("</") {
+            let name = parser.name()?;
+            parser.skip_whitespace();
+            if !parser.eat(">") {
+                return parser.error("expected '>'");
+            }
+
+            match stack.pop() {
+                Some((OwnedNode::Tag { name: open, .. }, _)) if open != name => {
+                    return Err(XmlError {
+                        offset: start,
+                        message: "closing tag doesn't match the open tag",
+                    });
+                }
+                Some((node, _)) => push!(node),
+                None => {
+                    return Err(XmlError {
+                        offset: start,
+                        message: "closing tag without a matching open tag",
+                    });
+                }
+            }
+        } else if parser.eat("<") {
+            let (node, self_closing) = parser.start_tag()?;
+            if self_closing {
+                push!(node);
+            } else {
+                stack.push((node, start));
+            }
+        } else {
+            let len = parser.rest().find('<').unwrap_or(parser.rest().len());
+            let raw = &parser.rest()[..len];
+            let text = parser.unescape(raw, start)?;
+            parser.pos += len;
+            push!(OwnedNode::Text(text));
+        }
+    }
+
+    if let Some((_, offset)) = stack.pop() {
+        return Err(XmlError {
+            offset,
+            message: "unclosed tag",
+        });
+    }
+
+    Ok(top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn round_trip() {
+        let input = "\x05\x06keyword1\x06kind=\"a<b\"\x05lemma\x05\x06\x05 x & y\
+            \x05\x06xml_elem\x05\x05\x06\x05";
+        let nodes = parse(input).unwrap();
+        let xml: String = nodes.iter().map(Node::to_xml).collect();
+        assert_eq!(
+            xml,
+            r#"<keyword1 kind="&quot;a&lt;b&quot;">lemma</keyword1> x &amp; y<xml_elem/>"#
+        );
+
+        let back = from_xml(&xml).unwrap();
+        let back: Vec<Node<'_>> = back.iter().map(OwnedNode::borrow).collect();
+        assert_eq!(back, nodes);
+    }
+
+    #[test]
+    fn markup_declarations() {
+        let nodes = from_xml(
+            "<?xml version=\"1.0\"?><!-- c --><a b='&#x3b1;'><![CDATA[<&>]]>&#955;</a>",
+        )
+        .unwrap();
+        assert_eq!(
+            nodes,
+            [OwnedNode::Tag {
+                name: "a".to_owned(),
+                attrs: vec![("b".to_owned(), "α".to_owned())],
+                children: vec![
+                    OwnedNode::Text("<&>".to_owned()),
+                    OwnedNode::Text("λ".to_owned()),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            from_xml("<a><b></a>").unwrap_err(),
+            XmlError {
+                offset: 6,
+                message: "closing tag doesn't match the open tag",
+            }
+        );
+        assert_eq!(from_xml("<a>&foo;</a>").unwrap_err().offset, 3);
+        assert_eq!(from_xml("x<a>").unwrap_err().offset, 1);
+    }
+}